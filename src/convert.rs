@@ -0,0 +1,306 @@
+//! Conversion of main.workflow into YAML workflows.
+//!
+//! Every action becomes a job, consisting of checkout step and the action itself.
+use crate::hir::{Action, ActionId, Event, Hir, Workflow};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+
+/// Checkout step, which populates workspace like v1 runner did
+const CHECKOUT_ACTION: &str = "actions/checkout@v4";
+
+/// Runner, used for converted jobs
+const RUNS_ON: &str = "ubuntu-latest";
+
+/// Converted workflow
+pub struct Converted {
+    /// File name inside `.github/workflows`
+    pub file_name: String,
+    pub yaml: String,
+    /// Things which could not be converted faithfully
+    pub warnings: Vec<String>,
+}
+
+/// Converts arbitrary name into identifier, matching `[A-Za-z_][A-Za-z0-9_-]*`
+fn sanitize_id(name: &str) -> String {
+    let mut out = String::new();
+    for ch in name.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '_' {
+            out.push(ch);
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let out = out.trim_matches('-');
+    if out.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_') {
+        out.to_string()
+    } else {
+        format!("_{}", out)
+    }
+}
+
+/// Quotes argument for YAML `args` string, which is split like shell does
+fn quote_arg(arg: &str) -> String {
+    let is_plain = |ch: char| ch.is_ascii_alphanumeric() || "-_./=:,+@%".contains(ch);
+    if !arg.is_empty() && arg.chars().all(is_plain) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn string(s: impl Into<String>) -> Value {
+    Value::String(s.into())
+}
+
+struct Converter<'a> {
+    hir: &'a Hir,
+    wf: &'a Workflow,
+    job_ids: HashMap<usize, String>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Converter<'a> {
+    fn warn(&mut self, msg: String) {
+        self.warnings.push(msg);
+    }
+
+    fn convert_on(&mut self) -> Value {
+        let mut on = Mapping::new();
+        let mut crons = vec![];
+        for ev in &self.wf.on {
+            match ev {
+                Event::Schedule(s) => {
+                    let mut item = Mapping::new();
+                    item.insert(string("cron"), string(s.to_string()));
+                    crons.push(Value::Mapping(item));
+                }
+                Event::RepositoryVulnerabilityAlert => self.warn(format!(
+                    "event {} has no YAML equivalent and is dropped",
+                    ev.name()
+                )),
+                ev => {
+                    on.insert(string(ev.name()), Value::Null);
+                }
+            }
+        }
+        if !crons.is_empty() {
+            on.insert(string("schedule"), Value::Sequence(crons));
+        }
+        // single event without filters is written in short form
+        if on.len() == 1 {
+            if let Some((name, Value::Null)) = on.iter().next() {
+                return name.clone();
+            }
+        }
+        Value::Mapping(on)
+    }
+
+    fn convert_step(&mut self, act: &Action) -> Value {
+        let mut step = Mapping::new();
+        step.insert(string("name"), string(act.name.clone()));
+        step.insert(string("uses"), string(act.uses.clone()));
+
+        let mut args = vec![];
+        let mut with = Mapping::new();
+        if let Some(runs) = &act.runs {
+            with.insert(string("entrypoint"), string(runs[0].clone()));
+            args.extend(runs[1..].iter().cloned());
+        }
+        if let Some(act_args) = &act.args {
+            args.extend(act_args.iter().cloned());
+        }
+        if !args.is_empty() {
+            let args: Vec<_> = args.iter().map(|arg| quote_arg(arg)).collect();
+            with.insert(string("args"), string(args.join(" ")));
+        }
+        if !act.uses.starts_with("docker://") {
+            // v1 actions needed only Dockerfile, while YAML workflows can't use actions without action.yml
+            let inputs = if with.is_empty() {
+                ""
+            } else {
+                "; runs and args are passed as inputs, which action.yml must declare"
+            };
+            self.warn(format!(
+                "action '{}': {} must contain action.yml to be used from YAML workflow{}",
+                &act.name, &act.uses, inputs
+            ));
+        }
+        if !with.is_empty() {
+            step.insert(string("with"), Value::Mapping(with));
+        }
+
+        let mut env_keys: Vec<_> = act.env.keys().collect();
+        env_keys.sort();
+        let mut env = Mapping::new();
+        for key in env_keys {
+            env.insert(string(key.clone()), string(act.env[key].clone()));
+        }
+        for secret in &act.secrets {
+            env.insert(
+                string(secret.clone()),
+                string(format!("${{{{ secrets.{} }}}}", secret)),
+            );
+        }
+        if !env.is_empty() {
+            step.insert(string("env"), Value::Mapping(env));
+        }
+        Value::Mapping(step)
+    }
+
+    fn convert_job(&mut self, id: ActionId) -> Value {
+        let act = self.hir.action(id);
+        let mut job = Mapping::new();
+        job.insert(string("runs-on"), string(RUNS_ON));
+        if !act.needs.is_empty() {
+            let needs = act
+                .needs
+                .iter()
+                .map(|dep| string(self.job_ids[&dep.as_inner()].clone()))
+                .collect();
+            job.insert(string("needs"), Value::Sequence(needs));
+        }
+        let mut checkout = Mapping::new();
+        checkout.insert(string("uses"), string(CHECKOUT_ACTION));
+        let steps = vec![Value::Mapping(checkout), self.convert_step(act)];
+        job.insert(string("steps"), Value::Sequence(steps));
+        Value::Mapping(job)
+    }
+
+    fn convert(mut self, order: &[ActionId]) -> Converted {
+        let mut used_ids = vec![];
+        for &id in order {
+            let base = sanitize_id(&self.hir.action(id).name);
+            let mut job_id = base.clone();
+            let mut suffix = 2;
+            while used_ids.contains(&job_id) {
+                job_id = format!("{}-{}", base, suffix);
+                suffix += 1;
+            }
+            used_ids.push(job_id.clone());
+            self.job_ids.insert(id.as_inner(), job_id);
+        }
+
+        let mut doc = Mapping::new();
+        doc.insert(string("name"), string(self.wf.name.clone()));
+        let on = self.convert_on();
+        doc.insert(string("on"), on);
+        let mut jobs = Mapping::new();
+        for &id in order {
+            let job = self.convert_job(id);
+            jobs.insert(string(self.job_ids[&id.as_inner()].clone()), job);
+        }
+        doc.insert(string("jobs"), Value::Mapping(jobs));
+
+        if order
+            .iter()
+            .any(|&id| !self.hir.action(id).needs.is_empty())
+        {
+            self.warn(format!(
+                "workflow '{}': jobs do not share workspace, so files created by an action are not visible to actions which need it",
+                &self.wf.name
+            ));
+        }
+
+        let yaml = serde_yaml::to_string(&Value::Mapping(doc)).expect("failed serialize workflow");
+        let file_name = format!("{}.yml", sanitize_id(&self.wf.name).to_ascii_lowercase());
+        Converted {
+            file_name,
+            yaml,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// Converts workflow; `order` must contain actions it resolves in scheduled order
+pub fn convert(hir: &Hir, wf: &Workflow, order: &[ActionId]) -> Converted {
+    let converter = Converter {
+        hir,
+        wf,
+        job_ids: HashMap::new(),
+        warnings: vec![],
+    };
+    converter.convert(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hir::{
+            self,
+            diag::{Diagnostics, Source},
+            parser, yaml,
+        },
+        scheduler,
+    };
+
+    #[test]
+    fn args_survive_conversion() {
+        let hcl = r#"
+workflow "ci" {
+  on = "push"
+  resolves = ["a"]
+}
+
+action "a" {
+  uses = "docker://alpine"
+  runs = ["sh", "-c", "echo \"it's\" $HOME"]
+  args = ["", "a\\b", "--flag=1"]
+}
+"#;
+        let defs = parser::parse(&Source::new("main.workflow", hcl.to_string())).unwrap();
+        let h = hir::build(defs).unwrap();
+        let wf = h.workflows().next().unwrap();
+        let order = scheduler::schedule(&h, wf).unwrap();
+        let converted = convert(&h, wf, &order);
+        assert!(converted.warnings.is_empty(), "{:?}", converted.warnings);
+
+        let source = Source::new(".github/workflows/ci.yml", converted.yaml);
+        let mut warnings = Diagnostics::new();
+        let lowered = hir::build(yaml::parse(&source, &mut warnings).unwrap()).unwrap();
+        assert!(warnings.is_empty(), "{}", warnings.warnings());
+        let act = lowered.action(lowered.find_action("ci/a/a").unwrap());
+        assert_eq!(act.runs.as_ref().unwrap(), &["sh"]);
+        assert_eq!(
+            act.args.as_ref().unwrap(),
+            &["-c", "echo \"it's\" $HOME", "", "a\\b", "--flag=1"]
+        );
+    }
+
+    #[test]
+    fn non_docker_actions_are_warned() {
+        let hcl = r#"
+workflow "ci" {
+  on = "push"
+  resolves = ["local", "remote", "image"]
+}
+
+action "local" {
+  uses = "./ci"
+}
+
+action "remote" {
+  uses = "actions/bin/sh@master"
+  args = ["ls"]
+}
+
+action "image" {
+  uses = "docker://alpine"
+  args = ["ls"]
+}
+"#;
+        let defs = parser::parse(&Source::new("main.workflow", hcl.to_string())).unwrap();
+        let h = hir::build(defs).unwrap();
+        let wf = h.workflows().next().unwrap();
+        let order = scheduler::schedule(&h, wf).unwrap();
+        let mut warnings = convert(&h, wf, &order).warnings;
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            [
+                "action 'local': ./ci must contain action.yml to be used from YAML workflow",
+                "action 'remote': actions/bin/sh@master must contain action.yml to be used from YAML workflow; \
+                 runs and args are passed as inputs, which action.yml must declare",
+            ]
+        );
+    }
+}
@@ -1,112 +1,558 @@
-use crate::hir::{ActionId, Hir};
+pub mod backend;
+pub mod event;
+mod git;
+mod image;
+mod output;
+
+pub use git::{get_git_info, GitInfo};
+pub use image::MirrorLocks;
+pub use output::Logger;
+
+use crate::{
+    hir::{ActionId, Hir, Workflow},
+    secrets::Secrets,
+};
+use backend::{Backend, Container, Volume};
 use std::{
+    collections::BTreeMap,
+    fmt, io,
     path::{Path, PathBuf},
-    process::exit,
+    sync::Arc,
 };
 
-fn provide_docker_image(s: &str) -> String {
-    if s.starts_with("docker://") {
-        s.trim_start_matches("docker://").to_string()
-    } else {
-        eprintln!("only pre-built image references (docker://*) are supported currently");
-        std::process::exit(1);
+/// Result of action execution
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Success,
+    Failure,
+    /// Action exited with code 78, which means "stop workflow, but don't fail it"
+    Neutral,
+    /// Action was not executed, because some of its dependencies did not succeed
+    Skipped,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+            Outcome::Neutral => "neutral",
+            Outcome::Skipped => "skipped",
+        }
     }
 }
 
-pub struct GitInfo {
-    commit: String,
+/// Error, which prevented action from running
+#[derive(Debug)]
+pub enum Error {
+    /// External program (`docker` or `git`) could not be started
+    Spawn(&'static str, io::Error),
+    /// External command exited unsuccessfully
+    CommandFailed(String),
+    /// `uses` of the action can not be turned into docker image
+    BadAction(String),
+    /// Action repository is not cached and network access is disabled
+    NotCached { url: String, mirror: PathBuf },
+    /// Filesystem operation failed
+    Io(String, io::Error),
+    /// Path can not be passed to external program, because it is not valid UTF-8
+    NonUtf8Path(PathBuf),
+    /// Output of `git` could not be parsed
+    GitOutput(String),
+    /// Secret, used by the action, has no value
+    MissingSecret(String),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Spawn(program, err) => write!(f, "failed start {}: {}", program, err),
+            Error::CommandFailed(msg) | Error::BadAction(msg) => f.write_str(msg),
+            Error::NotCached { url, mirror } => write!(
+                f,
+                "action repository {} is not cached and --offline is set\nhint: populate cache with `git clone --mirror {} {}`",
+                url,
+                url,
+                mirror.display()
+            ),
+            Error::Io(what, err) => write!(f, "failed {}: {}", what, err),
+            Error::NonUtf8Path(path) => write!(f, "path {} is not valid UTF-8", path.display()),
+            Error::GitOutput(msg) => write!(f, "unexpected git output: {}", msg),
+            Error::MissingSecret(name) => write!(f, "secret {} has no value", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Copies repository at `src` into workspace at `dest`, skipping ignored files
+pub fn copy_workspace(src: &Path, dest: &Path) -> io::Result<()> {
+    // hidden files are copied too, because local actions usually live in `.github`
+    let walker = ignore::WalkBuilder::new(src).hidden(false).build();
+    for item in walker {
+        let item = item.map_err(io::Error::other)?;
+        let is_file = match item.file_type() {
+            Some(s) => s.is_file(),
+            None => false,
+        };
+        if !is_file {
+            continue;
+        }
+        let path = item.path();
+        let rel_path = path
+            .strip_prefix(src)
+            .expect("walker yields paths inside src");
+        if rel_path.starts_with(".git") {
+            continue;
+        }
+        let dest_path = dest.join(rel_path);
+        std::fs::create_dir_all(dest_path.parent().unwrap())?;
+        std::fs::copy(path, &dest_path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "failed copy {} to {}: {}",
+                    path.display(),
+                    dest_path.display(),
+                    err
+                ),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Exit code, used by actions to signal neutral outcome
+const NEUTRAL_EXIT_CODE: i32 = 78;
+
 pub struct ExecOptions {
     pub git: GitInfo,
-    pub dir: PathBuf,
     pub dry: bool,
     pub home_dir: String,
     pub workspace_dir: String,
+    /// Directory with `event.json`, mounted to `/github/workflow`
+    pub workflow_dir: String,
+    pub secrets: Secrets,
+    /// Name of event, which triggered workflow
+    pub event_name: String,
+    /// Directory containing mirrors of action repositories
+    pub actions_cache: PathBuf,
+    /// Fail instead of cloning missing mirrors
+    pub offline: bool,
+    /// Shared by all actions of the run
+    pub mirror_locks: MirrorLocks,
+    /// Engine, which builds images and runs containers
+    pub backend: Arc<dyn Backend>,
 }
 
-pub fn get_git_info(dir: &Path) -> GitInfo {
-    let output = std::process::Command::new("git")
-        .args(&["rev-parse", "HEAD"])
-        .stderr(std::process::Stdio::inherit())
-        .current_dir(dir)
-        .output()
-        .expect("failed start git");
-    if !output.status.success() {
-        eprintln!("git didn't exit successfully");
-        exit(1);
-    }
-    let commit = String::from_utf8(output.stdout).expect("strange git output").trim().to_string();
-    GitInfo { commit }
-}
-
-pub fn execute(h: &Hir, act: ActionId, opts: &ExecOptions) {
+/// Runs action in container.
+///
+/// `Err` means that the action could not be started at all.
+pub fn execute(
+    h: &Hir,
+    wf: &Workflow,
+    act: ActionId,
+    opts: &ExecOptions,
+) -> Result<Outcome, Error> {
     let act = h.action(act);
-    println!("---executing action {}---", &act.name);
-    let image = provide_docker_image(&act.uses);
-    println!("Using docker image {}", &image);
-    let mut cmdline = vec![];
-    cmdline.push("run".to_string());
-    cmdline.push("--rm".to_string());
-
-    let mut env = act.env.clone();
+    let log = Logger::new(&act.name, opts.secrets.values());
+    let secrets = act
+        .secrets
+        .iter()
+        .map(|name| match opts.secrets.get(name) {
+            Some(value) => Ok((name.clone(), value.to_string())),
+            None => Err(Error::MissingSecret(name.clone())),
+        })
+        .collect::<Result<_, _>>()?;
+    let image = image::provide_docker_image(&act.uses, opts, &log)?;
+    log.out(format_args!("Using image {}", &image));
+
+    let mut env: BTreeMap<_, _> = act.env.clone().into_iter().collect();
     let mut add_var = |name: &str, value: &dyn AsRef<str>| {
         env.insert(name.to_string(), value.as_ref().to_string());
     };
     add_var("HOME", &"/github/home");
-    add_var("GITHUB_WORKFLOW", &h.workflow().name);
+    add_var("GITHUB_WORKFLOW", &wf.name);
     add_var("GITHUB_ACTION", &act.name);
-    add_var("GITHUB_EVENT_NAME", &"push");
+    add_var("GITHUB_EVENT_NAME", &opts.event_name);
     add_var("GITHUB_EVENT_PATH", &"/github/workflow/event.json");
     add_var("GITHUB_WORKSPACE", &"/github/workspace");
     add_var("GITHUB_SHA", &opts.git.commit);
+    add_var("GITHUB_REF", &opts.git.git_ref);
+    add_var("GITHUB_REPOSITORY", &opts.git.repository);
+    add_var("GITHUB_ACTOR", &opts.git.user.name);
 
     add_var("GH_ACTIONS_RUNNER_LOCAL", &"1");
 
-    for (key, val) in &env {
-        cmdline.push("--env".to_string());
-        cmdline.push(format!("{}={}", key, val));
-    }
-    cmdline.push("--workdir".to_string());
-    cmdline.push("/github/workspace".to_string());
-
-    cmdline.push("--volume".to_string());
-    cmdline.push(format!("{}:/github/workspace", &opts.workspace_dir));
-
-    cmdline.push("--volume".to_string());
-    cmdline.push(format!("{}:/github/home", &opts.home_dir));
+    let volume = |host: &str, container: &str, read_only| Volume {
+        host: host.to_string(),
+        container: container.to_string(),
+        read_only,
+    };
+    let volumes = vec![
+        volume(&opts.workspace_dir, "/github/workspace", false),
+        volume(&opts.home_dir, "/github/home", false),
+        volume(&opts.workflow_dir, "/github/workflow", true),
+    ];
 
-    let add_args;
-    match &act.runs {
+    let (entrypoint, args) = match &act.runs {
         Some(runs) => {
             let mut runs = runs.clone();
             if let Some(args) = &act.args {
                 runs.extend(args.iter().cloned());
             }
-            cmdline.push("--entrypoint".to_string());
-            cmdline.push(runs[0].clone());
-            add_args = runs[1..].to_vec();
+            (Some(runs[0].clone()), runs[1..].to_vec())
         }
-        None => {
-            if let Some(args) = &act.args {
-                add_args = args.clone();
-            } else {
-                add_args = vec![]
+        None => (None, act.args.clone().unwrap_or_default()),
+    };
+    let container = Container {
+        image,
+        entrypoint,
+        args,
+        env,
+        secrets,
+        workdir: "/github/workspace".to_string(),
+        volumes,
+    };
+    Ok(match opts.backend.run_container(&container, &log)? {
+        Some(0) => Outcome::Success,
+        Some(NEUTRAL_EXIT_CODE) => Outcome::Neutral,
+        _ => Outcome::Failure,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        backend::Mock,
+        git::{CommitInfo, Person},
+        *,
+    };
+    use crate::{
+        hir::{self, diag::Source, parser},
+        scheduler,
+    };
+    use std::collections::HashMap;
+
+    const WORKFLOW: &str = r#"
+workflow "ci" {
+  on = "push"
+  resolves = ["test"]
+}
+
+action "build" {
+  uses = "docker://rust"
+  runs = ["cargo", "build"]
+  env = {
+    RUST_LOG = "debug"
+  }
+}
+
+action "test" {
+  uses = "./ci"
+  needs = ["build"]
+  args = ["--verbose"]
+  secrets = ["TOKEN"]
+}
+"#;
+
+    fn load(src: &str) -> Hir {
+        let defs = parser::parse(&Source::new("main.workflow", src.to_string())).unwrap();
+        hir::build(defs).unwrap()
+    }
+
+    /// Workspace with local action `ci`
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("ci")).unwrap();
+        std::fs::write(dir.path().join("ci/Dockerfile"), "FROM alpine\n").unwrap();
+        dir
+    }
+
+    fn person() -> Person {
+        Person {
+            name: "dev".to_string(),
+            email: "dev@example.com".to_string(),
+        }
+    }
+
+    fn options(backend: Arc<dyn Backend>, workspace: &Path) -> ExecOptions {
+        let head = CommitInfo {
+            sha: "abc".to_string(),
+            tree: "def".to_string(),
+            message: "test".to_string(),
+            author: person(),
+            committer: person(),
+            commit_date: "2019-11-01T00:00:00+00:00".to_string(),
+            added: vec![],
+            removed: vec![],
+            modified: vec![],
+        };
+        let mut secrets = HashMap::new();
+        secrets.insert("TOKEN".to_string(), "hunter2".to_string());
+        ExecOptions {
+            git: GitInfo {
+                commit: "abc".to_string(),
+                git_ref: "refs/heads/master".to_string(),
+                parent: None,
+                head,
+                repository: "owner/repo".to_string(),
+                user: person(),
+            },
+            dry: false,
+            home_dir: "/tmp/home".to_string(),
+            workspace_dir: workspace.to_str().unwrap().to_string(),
+            workflow_dir: "/tmp/workflow".to_string(),
+            secrets: Secrets::new(secrets),
+            event_name: "push".to_string(),
+            actions_cache: PathBuf::from("/nonexistent"),
+            offline: true,
+            mirror_locks: MirrorLocks::new(),
+            backend,
+        }
+    }
+
+    /// Runs the first workflow and returns outcomes of actions by name
+    fn run_workflow(h: &Hir, opts: &ExecOptions) -> HashMap<String, Option<Outcome>> {
+        let wf = h.workflows().next().unwrap();
+        let order = scheduler::schedule(h, wf).unwrap();
+        let progress = scheduler::run(h, &order, 1, |act| execute(h, wf, act, opts).unwrap());
+        h.actions()
+            .enumerate()
+            .map(|(i, act)| (act.name.clone(), progress.outcome(ActionId::new(i))))
+            .collect()
+    }
+
+    #[test]
+    fn runs_containers_in_order() {
+        let h = load(WORKFLOW);
+        let ws = workspace();
+        let mock = Arc::new(Mock::new());
+        let outcomes = run_workflow(&h, &options(mock.clone(), ws.path()));
+        assert_eq!(outcomes["build"], Some(Outcome::Success));
+        assert_eq!(outcomes["test"], Some(Outcome::Success));
+
+        let containers = mock.containers();
+        assert_eq!(containers.len(), 2);
+        let build = &containers[0];
+        assert_eq!(build.image, "rust");
+        assert_eq!(build.entrypoint.as_deref(), Some("cargo"));
+        assert_eq!(build.args, ["build"]);
+        assert_eq!(build.env["RUST_LOG"], "debug");
+        assert_eq!(build.env["GITHUB_WORKFLOW"], "ci");
+        assert_eq!(build.env["GITHUB_ACTION"], "build");
+        assert_eq!(build.env["GITHUB_SHA"], "abc");
+        assert_eq!(build.env["GITHUB_REPOSITORY"], "owner/repo");
+        assert_eq!(build.workdir, "/github/workspace");
+        let workspace = build
+            .volumes
+            .iter()
+            .find(|vol| vol.container == "/github/workspace")
+            .unwrap();
+        assert_eq!(workspace.host, ws.path().to_str().unwrap());
+        assert!(!workspace.read_only);
+        assert!(build.secrets.is_empty());
+
+        let test = &containers[1];
+        assert_eq!(mock.built_images(), std::slice::from_ref(&test.image));
+        assert!(test.image.starts_with("gh-actions-local-runner/local-ci-"));
+        assert_eq!(test.entrypoint, None);
+        assert_eq!(test.args, ["--verbose"]);
+    }
+
+    #[test]
+    fn secrets_are_not_in_env() {
+        let h = load(WORKFLOW);
+        let ws = workspace();
+        let mock = Arc::new(Mock::new());
+        run_workflow(&h, &options(mock.clone(), ws.path()));
+        let test = &mock.containers()[1];
+        assert_eq!(test.secrets["TOKEN"], "hunter2");
+        assert!(!test.env.contains_key("TOKEN"));
+        assert!(test.env.values().all(|val| !val.contains("hunter2")));
+    }
+
+    #[test]
+    fn failure_skips_dependents() {
+        let h = load(WORKFLOW);
+        let ws = workspace();
+        let mock = Arc::new(Mock::new().exit_code("build", 1));
+        let outcomes = run_workflow(&h, &options(mock.clone(), ws.path()));
+        assert_eq!(outcomes["build"], Some(Outcome::Failure));
+        assert_eq!(outcomes["test"], Some(Outcome::Skipped));
+        assert_eq!(mock.containers().len(), 1);
+        assert!(mock.built_images().is_empty());
+    }
+
+    #[test]
+    fn neutral_exit_code() {
+        let h = load(WORKFLOW);
+        let ws = workspace();
+        let mock = Arc::new(Mock::new().exit_code("build", NEUTRAL_EXIT_CODE));
+        let outcomes = run_workflow(&h, &options(mock, ws.path()));
+        assert_eq!(outcomes["build"], Some(Outcome::Neutral));
+        assert_eq!(outcomes["test"], Some(Outcome::Skipped));
+    }
+
+    #[test]
+    fn local_action_without_dockerfile() {
+        let h = load(WORKFLOW);
+        let ws = tempfile::tempdir().unwrap();
+        let mock = Arc::new(Mock::new());
+        let opts = options(mock.clone(), ws.path());
+        let wf = h.workflows().next().unwrap();
+        let test = h.find_action("test").unwrap();
+        match execute(&h, wf, test, &opts) {
+            Err(Error::BadAction(msg)) => assert!(msg.contains("does not contain Dockerfile")),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert!(mock.containers().is_empty());
+    }
+
+    #[test]
+    fn missing_secret_is_error() {
+        let h = load(WORKFLOW);
+        let ws = workspace();
+        let mock = Arc::new(Mock::new());
+        let mut opts = options(mock.clone(), ws.path());
+        opts.secrets = Secrets::new(HashMap::new());
+        let wf = h.workflows().next().unwrap();
+        let test = h.find_action("test").unwrap();
+        match execute(&h, wf, test, &opts) {
+            Err(Error::MissingSecret(name)) => assert_eq!(name, "TOKEN"),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert!(mock.built_images().is_empty());
+        assert!(mock.containers().is_empty());
+    }
+
+    #[test]
+    fn offline_remote_action_is_not_cached() {
+        let h = load(
+            r#"
+workflow "ci" {
+  on = "push"
+  resolves = ["sh"]
+}
+
+action "sh" {
+  uses = "actions/bin/sh@master"
+}
+"#,
+        );
+        let ws = workspace();
+        let mock = Arc::new(Mock::new());
+        let opts = options(mock.clone(), ws.path());
+        let wf = h.workflows().next().unwrap();
+        let sh = h.find_action("sh").unwrap();
+        match execute(&h, wf, sh, &opts) {
+            Err(Error::NotCached { url, .. }) => {
+                assert_eq!(url, "https://github.com/actions/bin.git")
             }
+            res => panic!("unexpected result: {:?}", res),
         }
+        assert!(mock.containers().is_empty());
     }
-    cmdline.push(image);
-    cmdline.extend(add_args);
-    println!("will run: {}", cmdline.join(" "));
-    if opts.dry {
-        return;
-    }
-    let st = match std::process::Command::new("docker").args(cmdline).status() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("failed start docker: {}", e);
-            std::process::exit(1);
+
+    #[test]
+    fn remote_action_is_checked() {
+        let ws = workspace();
+        for (uses, expected) in &[
+            ("actions/bin/../../etc@master", "escapes repository"),
+            ("actions/../bin@master", "escapes repository"),
+            (
+                "actions/bin/sh@--upload-pack=touch",
+                "must not start with '-'",
+            ),
+        ] {
+            let h = load(&format!(
+                "workflow \"ci\" {{\n  on = \"push\"\n  resolves = [\"sh\"]\n}}\n\naction \"sh\" {{\n  uses = \"{}\"\n}}\n",
+                uses
+            ));
+            let mock = Arc::new(Mock::new());
+            let opts = options(mock.clone(), ws.path());
+            let wf = h.workflows().next().unwrap();
+            let sh = h.find_action("sh").unwrap();
+            match execute(&h, wf, sh, &opts) {
+                Err(Error::BadAction(msg)) => assert!(msg.contains(expected), "{}", msg),
+                res => panic!("unexpected result for {}: {:?}", uses, res),
+            }
+            assert!(mock.containers().is_empty());
         }
-    };
-    std::process::exit(st.code().unwrap_or(1));
+    }
+
+    #[test]
+    fn local_images_are_distinct() {
+        let dirs = ["a-b", "a/b", "Lint", "lint"];
+        let ws = tempfile::tempdir().unwrap();
+        let mut src =
+            "workflow \"ci\" {\n  on = \"push\"\n  resolves = [\"0\", \"1\", \"2\", \"3\"]\n}\n"
+                .to_string();
+        for (i, dir) in dirs.iter().enumerate() {
+            std::fs::create_dir_all(ws.path().join(dir)).unwrap();
+            std::fs::write(ws.path().join(dir).join("Dockerfile"), "FROM alpine\n").unwrap();
+            src.push_str(&format!(
+                "\naction \"{}\" {{\n  uses = \"./{}\"\n}}\n",
+                i, dir
+            ));
+        }
+        let h = load(&src);
+        let mock = Arc::new(Mock::new());
+        run_workflow(&h, &options(mock.clone(), ws.path()));
+        let mut images = mock.built_images();
+        assert_eq!(images.len(), dirs.len());
+        images.sort();
+        images.dedup();
+        assert_eq!(images.len(), dirs.len(), "{:?}", images);
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let st = std::process::Command::new("git")
+            .args(["-c", "user.name=dev", "-c", "user.email=dev@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(st.status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn concurrent_actions_share_mirror() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join("Dockerfile"), "FROM alpine\n").unwrap();
+        git(repo.path(), &["init", "--quiet", "--initial-branch=master"]);
+        git(repo.path(), &["add", "Dockerfile"]);
+        git(repo.path(), &["commit", "--quiet", "-m", "init"]);
+        let cache = tempfile::tempdir().unwrap();
+        let repo_path = repo.path().to_str().unwrap();
+        git(
+            cache.path(),
+            &["clone", "--quiet", "--mirror", repo_path, "owner/repo.git"],
+        );
+
+        let names: Vec<_> = (0..8).map(|i| format!("sh{}", i)).collect();
+        let quoted: Vec<_> = names.iter().map(|name| format!("\"{}\"", name)).collect();
+        let mut src = format!(
+            "workflow \"ci\" {{\n  on = \"push\"\n  resolves = [{}]\n}}\n",
+            quoted.join(", ")
+        );
+        for name in &names {
+            src.push_str(&format!(
+                "\naction \"{}\" {{\n  uses = \"owner/repo@master\"\n}}\n",
+                name
+            ));
+        }
+        let h = load(&src);
+        let ws = workspace();
+        let mock = Arc::new(Mock::new());
+        let mut opts = options(mock.clone(), ws.path());
+        opts.actions_cache = cache.path().to_path_buf();
+        let wf = h.workflows().next().unwrap();
+        let order = scheduler::schedule(&h, wf).unwrap();
+        let progress = scheduler::run(&h, &order, 8, |act| execute(&h, wf, act, &opts).unwrap());
+        for &act in &order {
+            assert_eq!(progress.outcome(act), Some(Outcome::Success));
+        }
+        assert_eq!(mock.built_images().len(), names.len());
+    }
 }
@@ -0,0 +1,216 @@
+//! Container engines, which build images and run actions
+use super::{output::Logger, Error};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    process::Command,
+    sync::Mutex,
+};
+
+/// Directory, mounted into container
+#[derive(Clone, Debug)]
+pub struct Volume {
+    pub host: String,
+    pub container: String,
+    pub read_only: bool,
+}
+
+/// Container, which runs single action
+#[derive(Clone, Debug)]
+pub struct Container {
+    pub image: String,
+    /// Overrides entrypoint of the image
+    pub entrypoint: Option<String>,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    /// Secret variables; their values must never appear in command lines or logs
+    pub secrets: BTreeMap<String, String>,
+    pub workdir: String,
+    pub volumes: Vec<Volume>,
+}
+
+pub trait Backend: Send + Sync {
+    /// Builds image from Dockerfile in `context` and tags it with `tag`
+    fn build_image(&self, context: &Path, tag: &str, log: &Logger) -> Result<(), Error>;
+
+    /// Runs container until it exits, forwarding its output to `log`.
+    /// Returns exit code, or `None` if container was killed by signal.
+    fn run_container(&self, container: &Container, log: &Logger) -> Result<Option<i32>, Error>;
+}
+
+/// Builds image with docker-compatible CLI
+fn cli_build(
+    program: &'static str,
+    dry: bool,
+    context: &Path,
+    tag: &str,
+    log: &Logger,
+) -> Result<(), Error> {
+    let context = context
+        .to_str()
+        .ok_or_else(|| Error::NonUtf8Path(context.to_path_buf()))?;
+    let cmdline = ["build", "--tag", tag, context];
+    log.out(format_args!(
+        "will build: {} {}",
+        program,
+        cmdline.join(" ")
+    ));
+    if dry {
+        return Ok(());
+    }
+    let st = log
+        .run(Command::new(program).args(cmdline))
+        .map_err(|e| Error::Spawn(program, e))?;
+    if !st.success() {
+        return Err(Error::CommandFailed(format!(
+            "failed to build image for {}",
+            context
+        )));
+    }
+    Ok(())
+}
+
+/// Runs container with docker-compatible CLI
+fn cli_run(
+    program: &'static str,
+    dry: bool,
+    extra_args: &[&str],
+    c: &Container,
+    log: &Logger,
+) -> Result<Option<i32>, Error> {
+    let mut cmdline = vec!["run".to_string(), "--rm".to_string()];
+    cmdline.extend(extra_args.iter().map(ToString::to_string));
+    for (key, val) in &c.env {
+        cmdline.push("--env".to_string());
+        cmdline.push(format!("{}={}", key, val));
+    }
+    // secret values are passed to the CLI through its environment,
+    // so they never appear in the command line
+    for name in c.secrets.keys() {
+        cmdline.push("--env".to_string());
+        cmdline.push(name.clone());
+    }
+    cmdline.push("--workdir".to_string());
+    cmdline.push(c.workdir.clone());
+    for vol in &c.volumes {
+        cmdline.push("--volume".to_string());
+        let mode = if vol.read_only { ":ro" } else { "" };
+        cmdline.push(format!("{}:{}{}", vol.host, vol.container, mode));
+    }
+    if let Some(entrypoint) = &c.entrypoint {
+        cmdline.push("--entrypoint".to_string());
+        cmdline.push(entrypoint.clone());
+    }
+    cmdline.push(c.image.clone());
+    cmdline.extend(c.args.iter().cloned());
+    log.out(format_args!("will run: {} {}", program, cmdline.join(" ")));
+    if dry {
+        return Ok(Some(0));
+    }
+    let mut cmd = Command::new(program);
+    cmd.args(cmdline).envs(&c.secrets);
+    let st = log.run(&mut cmd).map_err(|e| Error::Spawn(program, e))?;
+    Ok(st.code())
+}
+
+/// Runs actions with `docker` CLI
+pub struct Docker {
+    /// Only print commands
+    dry: bool,
+}
+
+impl Docker {
+    pub fn new(dry: bool) -> Docker {
+        Docker { dry }
+    }
+}
+
+impl Backend for Docker {
+    fn build_image(&self, context: &Path, tag: &str, log: &Logger) -> Result<(), Error> {
+        cli_build("docker", self.dry, context, tag, log)
+    }
+
+    fn run_container(&self, container: &Container, log: &Logger) -> Result<Option<i32>, Error> {
+        cli_run("docker", self.dry, &[], container, log)
+    }
+}
+
+/// Runs actions with `podman` CLI, which also works without root
+pub struct Podman {
+    /// Only print commands
+    dry: bool,
+}
+
+impl Podman {
+    pub fn new(dry: bool) -> Podman {
+        Podman { dry }
+    }
+}
+
+impl Backend for Podman {
+    fn build_image(&self, context: &Path, tag: &str, log: &Logger) -> Result<(), Error> {
+        cli_build("podman", self.dry, context, tag, log)
+    }
+
+    fn run_container(&self, container: &Container, log: &Logger) -> Result<Option<i32>, Error> {
+        // SELinux labels of the host would deny access to mounted workspace.
+        // `keep-id` runs actions with uid of the user, so files they create
+        // in mounted directories stay owned by the user on the host.
+        cli_run(
+            "podman",
+            self.dry,
+            &["--security-opt", "label=disable", "--userns=keep-id"],
+            container,
+            log,
+        )
+    }
+}
+
+/// Backend, which records requests instead of running anything
+#[derive(Default)]
+pub struct Mock {
+    /// Exit codes by action name; other actions exit with 0
+    exit_codes: HashMap<String, i32>,
+    built: Mutex<Vec<String>>,
+    containers: Mutex<Vec<Container>>,
+}
+
+impl Mock {
+    pub fn new() -> Mock {
+        Mock::default()
+    }
+
+    /// Makes container of the action exit with given code
+    pub fn exit_code(mut self, action: &str, code: i32) -> Mock {
+        self.exit_codes.insert(action.to_string(), code);
+        self
+    }
+
+    /// Returns tags of built images
+    pub fn built_images(&self) -> Vec<String> {
+        self.built.lock().unwrap().clone()
+    }
+
+    /// Returns containers in order they were run
+    pub fn containers(&self) -> Vec<Container> {
+        self.containers.lock().unwrap().clone()
+    }
+}
+
+impl Backend for Mock {
+    fn build_image(&self, _context: &Path, tag: &str, _log: &Logger) -> Result<(), Error> {
+        self.built.lock().unwrap().push(tag.to_string());
+        Ok(())
+    }
+
+    fn run_container(&self, container: &Container, _log: &Logger) -> Result<Option<i32>, Error> {
+        self.containers.lock().unwrap().push(container.clone());
+        let code = container
+            .env
+            .get("GITHUB_ACTION")
+            .and_then(|action| self.exit_codes.get(action))
+            .copied()
+            .unwrap_or(0);
+        Ok(Some(code))
+    }
+}
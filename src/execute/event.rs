@@ -0,0 +1,130 @@
+use super::git::{CommitInfo, GitInfo, Person};
+use serde_json::{json, Value};
+
+/// Commit hash GitHub uses for missing `before` commit
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+fn person_json(p: &Person) -> Value {
+    json!({
+        "name": p.name,
+        "email": p.email,
+    })
+}
+
+fn commit_json(c: &CommitInfo, repository: &str) -> Value {
+    json!({
+        "id": c.sha,
+        "tree_id": c.tree,
+        "distinct": true,
+        "message": c.message,
+        "timestamp": c.commit_date,
+        "url": format!("https://github.com/{}/commit/{}", repository, c.sha),
+        "author": person_json(&c.author),
+        "committer": person_json(&c.committer),
+        "added": c.added,
+        "removed": c.removed,
+        "modified": c.modified,
+    })
+}
+
+/// Synthesizes payload of `push` event, which pushed current HEAD
+pub fn push_payload(git: &GitInfo) -> Value {
+    let mut repo_parts = git.repository.splitn(2, '/');
+    let owner = repo_parts.next().unwrap_or_default();
+    let name = repo_parts.next().unwrap_or_default();
+    let before = git.parent.as_deref().unwrap_or(NULL_SHA);
+    let head_commit = commit_json(&git.head, &git.repository);
+    json!({
+        "ref": git.git_ref,
+        "before": before,
+        "after": git.commit,
+        "created": git.parent.is_none(),
+        "deleted": false,
+        "forced": false,
+        "base_ref": null,
+        "compare": format!(
+            "https://github.com/{}/compare/{}...{}",
+            git.repository, before, git.commit
+        ),
+        "commits": [head_commit.clone()],
+        "head_commit": head_commit,
+        "repository": {
+            "name": name,
+            "full_name": git.repository,
+            "owner": {
+                "name": owner,
+                "login": owner,
+            },
+            "html_url": format!("https://github.com/{}", git.repository),
+        },
+        "pusher": person_json(&git.user),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(name: &str) -> Person {
+        Person {
+            name: name.to_string(),
+            email: format!("{}@example.com", name),
+        }
+    }
+
+    fn git_info(parent: Option<&str>) -> GitInfo {
+        GitInfo {
+            commit: "abc".to_string(),
+            git_ref: "refs/heads/master".to_string(),
+            parent: parent.map(ToString::to_string),
+            head: CommitInfo {
+                sha: "abc".to_string(),
+                tree: "def".to_string(),
+                message: "fix bug".to_string(),
+                author: person("author"),
+                committer: person("committer"),
+                commit_date: "2019-11-01T00:00:00+00:00".to_string(),
+                added: vec!["new.rs".to_string()],
+                removed: vec![],
+                modified: vec!["lib.rs".to_string()],
+            },
+            repository: "owner/repo".to_string(),
+            user: person("pusher"),
+        }
+    }
+
+    #[test]
+    fn push_after_parent() {
+        let payload = push_payload(&git_info(Some("123")));
+        assert_eq!(payload["ref"], "refs/heads/master");
+        assert_eq!(payload["before"], "123");
+        assert_eq!(payload["after"], "abc");
+        assert_eq!(payload["created"], false);
+        assert_eq!(
+            payload["compare"],
+            "https://github.com/owner/repo/compare/123...abc"
+        );
+        assert_eq!(payload["repository"]["name"], "repo");
+        assert_eq!(payload["repository"]["owner"]["login"], "owner");
+        assert_eq!(payload["pusher"]["name"], "pusher");
+
+        let head = &payload["head_commit"];
+        assert_eq!(head["id"], "abc");
+        assert_eq!(head["tree_id"], "def");
+        assert_eq!(head["message"], "fix bug");
+        assert_eq!(head["timestamp"], "2019-11-01T00:00:00+00:00");
+        assert_eq!(head["url"], "https://github.com/owner/repo/commit/abc");
+        assert_eq!(head["author"]["email"], "author@example.com");
+        assert_eq!(head["committer"]["name"], "committer");
+        assert_eq!(head["added"], json!(["new.rs"]));
+        assert_eq!(head["modified"], json!(["lib.rs"]));
+        assert_eq!(payload["commits"], json!([head.clone()]));
+    }
+
+    #[test]
+    fn push_of_root_commit() {
+        let payload = push_payload(&git_info(None));
+        assert_eq!(payload["before"], NULL_SHA);
+        assert_eq!(payload["created"], true);
+    }
+}
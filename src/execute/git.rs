@@ -0,0 +1,203 @@
+use super::Error;
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+pub struct Person {
+    pub name: String,
+    pub email: String,
+}
+
+pub struct CommitInfo {
+    pub sha: String,
+    pub tree: String,
+    pub message: String,
+    pub author: Person,
+    pub committer: Person,
+    pub commit_date: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+pub struct GitInfo {
+    pub commit: String,
+    /// Full name of checked out ref, e.g. `refs/heads/master`.
+    /// For detached HEAD equals to commit hash.
+    pub git_ref: String,
+    /// Parent of HEAD, `None` for root commits
+    pub parent: Option<String>,
+    pub head: CommitInfo,
+    /// `owner/repo`, taken from `origin` remote if it points to GitHub
+    pub repository: String,
+    pub user: Person,
+}
+
+/// Runs git and returns its stdout
+fn git(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .args(args)
+        .stdin(Stdio::null())
+        .current_dir(dir)
+        .output()
+        .map_err(|e| Error::Spawn("git", e))?;
+    if !output.status.success() {
+        return Err(Error::CommandFailed(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| Error::GitOutput(format!("git {} printed invalid UTF-8", args.join(" "))))?;
+    Ok(stdout.trim_end().to_string())
+}
+
+fn get_commit_info(dir: &Path, sha: &str) -> Result<CommitInfo, Error> {
+    let info = git(
+        dir,
+        &[
+            "show",
+            "--no-patch",
+            "--format=%T%x00%an%x00%ae%x00%cn%x00%ce%x00%cI%x00%B",
+            sha,
+        ],
+    )?;
+    let mut parts = info.splitn(7, '\0').map(ToOwned::to_owned);
+    let mut next = || {
+        parts
+            .next()
+            .ok_or_else(|| Error::GitOutput(format!("truncated info of commit {}", sha)))
+    };
+    let tree = next()?;
+    let author = Person {
+        name: next()?,
+        email: next()?,
+    };
+    let committer = Person {
+        name: next()?,
+        email: next()?,
+    };
+    let commit_date = next()?;
+    let message = next()?.trim_end().to_string();
+
+    let mut added = vec![];
+    let mut removed = vec![];
+    let mut modified = vec![];
+    let changes = git(
+        dir,
+        &[
+            "diff-tree",
+            "--no-commit-id",
+            "--name-status",
+            "--root",
+            "-r",
+            sha,
+        ],
+    )?;
+    for line in changes.lines() {
+        let mut fields = line.splitn(2, '\t');
+        let status = fields.next().unwrap_or_default();
+        let path = match fields.next() {
+            Some(p) => p.to_string(),
+            None => continue,
+        };
+        match status {
+            "A" => added.push(path),
+            "D" => removed.push(path),
+            _ => modified.push(path),
+        }
+    }
+    Ok(CommitInfo {
+        sha: sha.to_string(),
+        tree,
+        message,
+        author,
+        committer,
+        commit_date,
+        added,
+        removed,
+        modified,
+    })
+}
+
+/// Extracts `owner/repo` from GitHub remote URL
+fn parse_github_url(url: &str) -> Option<String> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("git@github.com:"))
+        .or_else(|| url.strip_prefix("ssh://git@github.com/"))?;
+    let path = path.trim_end_matches('/').trim_end_matches(".git");
+    if path.split('/').count() != 2 {
+        return None;
+    }
+    Some(path.to_string())
+}
+
+pub fn get_git_info(dir: &Path) -> Result<GitInfo, Error> {
+    let commit = git(dir, &["rev-parse", "HEAD"])?;
+    let git_ref = git(dir, &["symbolic-ref", "--quiet", "HEAD"]).unwrap_or_else(|_| commit.clone());
+    let parent = git(dir, &["rev-parse", "--quiet", "--verify", "HEAD^"]).ok();
+    let head = get_commit_info(dir, &commit)?;
+    let repository = git(dir, &["remote", "get-url", "origin"])
+        .ok()
+        .as_deref()
+        .and_then(parse_github_url)
+        .unwrap_or_else(|| {
+            let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+            let name = dir
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "repo".to_string());
+            format!("local/{}", name)
+        });
+    let user = Person {
+        name: git(dir, &["config", "user.name"]).unwrap_or_else(|_| head.author.name.clone()),
+        email: git(dir, &["config", "user.email"]).unwrap_or_else(|_| head.author.email.clone()),
+    };
+    Ok(GitInfo {
+        commit,
+        git_ref,
+        parent,
+        head,
+        repository,
+        user,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn github_urls() {
+        for url in &[
+            "https://github.com/owner/repo",
+            "https://github.com/owner/repo.git",
+            "https://github.com/owner/repo/",
+            "git@github.com:owner/repo.git",
+            "ssh://git@github.com/owner/repo.git",
+        ] {
+            assert_eq!(
+                parse_github_url(url).as_deref(),
+                Some("owner/repo"),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn other_urls() {
+        for url in &[
+            "https://gitlab.com/owner/repo.git",
+            "git@gitlab.com:owner/repo.git",
+            "/home/user/repo",
+            "https://github.com/owner",
+            "https://github.com/owner/repo/tree/master",
+        ] {
+            assert_eq!(parse_github_url(url), None, "{}", url);
+        }
+    }
+}
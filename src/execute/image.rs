@@ -0,0 +1,309 @@
+use super::{output::Logger, Error, ExecOptions};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+/// Prefix of all image names built by runner
+const IMAGE_PREFIX: &str = "gh-actions-local-runner";
+
+/// Converts arbitrary path into string, usable as docker image name component
+fn sanitize_path(p: &Path) -> String {
+    let mut out = String::new();
+    for comp in p.components() {
+        let comp = match comp {
+            Component::Normal(c) => c.to_string_lossy(),
+            _ => continue,
+        };
+        for ch in comp.chars() {
+            let ch = ch.to_ascii_lowercase();
+            if ch.is_ascii_alphanumeric() {
+                out.push(ch);
+            } else if !out.is_empty() && !out.ends_with('-') {
+                out.push('-');
+            }
+        }
+        if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_end_matches('-').to_string()
+}
+
+/// Joins normal components of the path with `/`, so equivalent paths are equal
+fn normalize_path(p: &Path) -> String {
+    let comps: Vec<_> = p
+        .components()
+        .filter_map(|comp| match comp {
+            Component::Normal(c) => Some(c.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    comps.join("/")
+}
+
+/// Returns short FNV-1a hash of the string. Sanitized names of different paths
+/// may coincide, so hash is appended to them to keep image names distinct.
+fn short_hash(s: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in s.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:08x}", hash >> 32)
+}
+
+/// Converts git ref into string, usable as docker image tag
+fn sanitize_ref(r: &str) -> String {
+    let out: String = r
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '_' || ch == '.' || ch == '-' {
+                ch
+            } else {
+                '-'
+            }
+        })
+        .take(128)
+        .collect();
+    out.trim_start_matches(['.', '-']).to_string()
+}
+
+fn run_git(cmd: &mut Command, log: &Logger) -> Result<(), Error> {
+    let st = log.run(cmd).map_err(|e| Error::Spawn("git", e))?;
+    if !st.success() {
+        return Err(Error::CommandFailed(format!("git failed: {:?}", cmd)));
+    }
+    Ok(())
+}
+
+/// Builds image from Dockerfile in `context` and returns its tag
+fn build_image(
+    context: &Path,
+    tag: &str,
+    opts: &ExecOptions,
+    log: &Logger,
+) -> Result<String, Error> {
+    if !context.join("Dockerfile").is_file() {
+        return Err(Error::BadAction(format!(
+            "action directory {} does not contain Dockerfile",
+            context.display()
+        )));
+    }
+    opts.backend.build_image(context, tag, log)?;
+    Ok(tag.to_string())
+}
+
+/// Action is defined in the repository itself, e.g. `./.github/actions/lint`
+fn provide_local(path: &str, opts: &ExecOptions, log: &Logger) -> Result<String, Error> {
+    let rel = Path::new(path.trim_start_matches("./"));
+    if rel.components().any(|c| c == Component::ParentDir) {
+        return Err(Error::BadAction(format!(
+            "local action path {} escapes workspace",
+            path
+        )));
+    }
+    let tag = format!(
+        "{}/local-{}-{}",
+        IMAGE_PREFIX,
+        sanitize_path(rel),
+        short_hash(&normalize_path(rel))
+    );
+    build_image(&Path::new(&opts.workspace_dir).join(rel), &tag, opts, log)
+}
+
+/// Parsed `owner/repo/path@ref` reference
+struct RemoteRef<'a> {
+    owner: &'a str,
+    repo: &'a str,
+    path: &'a str,
+    git_ref: &'a str,
+}
+
+impl<'a> RemoteRef<'a> {
+    fn parse(uses: &'a str) -> Option<RemoteRef<'a>> {
+        let at = uses.rfind('@')?;
+        let (repo_path, git_ref) = (&uses[..at], &uses[at + 1..]);
+        let mut parts = repo_path.splitn(3, '/');
+        let owner = parts.next()?;
+        let repo = parts.next()?;
+        let path = parts.next().unwrap_or("").trim_end_matches('/');
+        if owner.is_empty() || repo.is_empty() || git_ref.is_empty() {
+            return None;
+        }
+        Some(RemoteRef {
+            owner,
+            repo,
+            path,
+            git_ref,
+        })
+    }
+
+    /// Rejects references, which could escape actions cache or checkout,
+    /// or be taken by git for an option
+    fn check(&self) -> Result<(), Error> {
+        let escapes = [self.owner, self.repo]
+            .iter()
+            .any(|s| *s == "." || *s == "..")
+            || Path::new(self.path)
+                .components()
+                .any(|c| c != Component::CurDir && !matches!(c, Component::Normal(_)));
+        if escapes {
+            return Err(Error::BadAction(format!(
+                "remote action path {}/{}/{} escapes repository",
+                self.owner, self.repo, self.path
+            )));
+        }
+        if self.git_ref.starts_with('-') {
+            return Err(Error::BadAction(format!(
+                "remote action ref {} must not start with '-'",
+                self.git_ref
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Serializes preparation of each action repository mirror.
+///
+/// Actions run concurrently and often use the same repository, so without it
+/// they would clone or fetch into the same directory at once.
+#[derive(Default)]
+pub struct MirrorLocks {
+    /// Mirror path -> whether mirror was already cloned or fetched by this run
+    mirrors: Mutex<HashMap<PathBuf, Arc<Mutex<bool>>>>,
+}
+
+impl MirrorLocks {
+    pub fn new() -> MirrorLocks {
+        MirrorLocks::default()
+    }
+
+    fn get(&self, mirror: &Path) -> Arc<Mutex<bool>> {
+        let mut mirrors = self.mirrors.lock().unwrap_or_else(|e| e.into_inner());
+        mirrors.entry(mirror.to_path_buf()).or_default().clone()
+    }
+}
+
+/// Makes sure mirror of the action repository is present in cache and up to date.
+/// Returns path to the mirror, or `None` if it was not cloned because of dry run.
+fn provide_mirror(
+    r: &RemoteRef,
+    opts: &ExecOptions,
+    log: &Logger,
+) -> Result<Option<PathBuf>, Error> {
+    let parent = opts.actions_cache.join(r.owner);
+    let mirror = parent.join(format!("{}.git", r.repo));
+    let lock = opts.mirror_locks.get(&mirror);
+    // git failure while holding the lock does not leave the mirror inconsistent
+    let mut up_to_date = lock.lock().unwrap_or_else(|e| e.into_inner());
+    if *up_to_date {
+        return Ok(Some(mirror));
+    }
+    if mirror.exists() {
+        if !opts.offline && !opts.dry {
+            run_git(
+                Command::new("git")
+                    .arg("--git-dir")
+                    .arg(&mirror)
+                    .args(["fetch", "--quiet", "--prune"]),
+                log,
+            )?;
+        }
+        *up_to_date = true;
+        return Ok(Some(mirror));
+    }
+    let url = format!("https://github.com/{}/{}.git", r.owner, r.repo);
+    if opts.offline {
+        return Err(Error::NotCached { url, mirror });
+    }
+    log.out(format_args!("will clone: {} to {}", url, mirror.display()));
+    if opts.dry {
+        return Ok(None);
+    }
+    // clone is moved into place when it is complete, so interrupted clone is never used
+    std::fs::create_dir_all(&parent)
+        .map_err(|e| Error::Io(format!("create {}", parent.display()), e))?;
+    let tmp = tempfile::Builder::new()
+        .prefix(".clone-")
+        .tempdir_in(&parent)
+        .map_err(|e| Error::Io(format!("create temp dir in {}", parent.display()), e))?;
+    let tmp_mirror = tmp.path().join("mirror.git");
+    run_git(
+        Command::new("git")
+            .args(["clone", "--quiet", "--mirror", &url])
+            .arg(&tmp_mirror),
+        log,
+    )?;
+    if let Err(err) = std::fs::rename(&tmp_mirror, &mirror) {
+        // other runner process could clone it meanwhile
+        if !mirror.exists() {
+            return Err(Error::Io(
+                format!("move clone to {}", mirror.display()),
+                err,
+            ));
+        }
+    }
+    *up_to_date = true;
+    Ok(Some(mirror))
+}
+
+/// Action is defined in other repository, e.g. `actions/bin/sh@master`
+fn provide_remote(r: &RemoteRef, opts: &ExecOptions, log: &Logger) -> Result<String, Error> {
+    r.check()?;
+    let repo_path = Path::new(r.owner).join(r.repo).join(r.path);
+    let tag = format!(
+        "{}/remote-{}-{}:{}",
+        IMAGE_PREFIX,
+        sanitize_path(&repo_path),
+        short_hash(&format!("{}@{}", normalize_path(&repo_path), r.git_ref)),
+        sanitize_ref(r.git_ref)
+    );
+    let mirror = match provide_mirror(r, opts, log)? {
+        Some(m) => m,
+        None => return Ok(tag),
+    };
+    let checkout_dir =
+        tempfile::tempdir().map_err(|e| Error::Io("allocate checkout dir".to_string(), e))?;
+    let checkout_path = checkout_dir.path();
+    run_git(
+        Command::new("git")
+            .args(["clone", "--quiet", "--shared", "--no-checkout"])
+            .arg(&mirror)
+            .arg(checkout_path),
+        log,
+    )?;
+    run_git(
+        Command::new("git")
+            .arg("-C")
+            .arg(checkout_path)
+            .args(["-c", "advice.detachedHead=false", "checkout", "--quiet"])
+            .arg(r.git_ref)
+            // `--` after the ref makes git take it for a revision, never for a path
+            .arg("--"),
+        log,
+    )?;
+    build_image(&checkout_path.join(r.path), &tag, opts, log)
+}
+
+/// Returns name of image, which should be used to run action with given `uses`
+pub(super) fn provide_docker_image(
+    uses: &str,
+    opts: &ExecOptions,
+    log: &Logger,
+) -> Result<String, Error> {
+    if uses.starts_with("docker://") {
+        Ok(uses.trim_start_matches("docker://").to_string())
+    } else if uses.starts_with("./") {
+        provide_local(uses, opts, log)
+    } else if let Some(r) = RemoteRef::parse(uses) {
+        provide_remote(&r, opts, log)
+    } else {
+        Err(Error::BadAction(format!(
+            "unsupported action reference: {}",
+            uses
+        )))
+    }
+}
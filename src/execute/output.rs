@@ -0,0 +1,141 @@
+use std::{
+    fmt::Display,
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Command, ExitStatus, Stdio},
+};
+
+/// Replacement for secret values in output
+const MASK: &str = "***";
+
+/// Prints output of a single action.
+///
+/// Actions may run concurrently, so each line is prefixed with action name.
+/// Secret values are replaced with `***`.
+pub struct Logger<'a> {
+    prefix: String,
+    /// Strings to mask, longest first
+    secrets: Vec<&'a str>,
+}
+
+impl<'a> Logger<'a> {
+    pub fn new(action_name: &str, secrets: impl Iterator<Item = &'a str>) -> Logger<'a> {
+        // output is masked line by line, so each line of multi-line secret is masked separately
+        let mut secrets: Vec<_> = secrets
+            .flat_map(str::lines)
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect();
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        Logger {
+            prefix: format!("[{}]", action_name),
+            secrets,
+        }
+    }
+
+    fn mask(&self, msg: impl Display) -> String {
+        let mut msg = msg.to_string();
+        for secret in &self.secrets {
+            if msg.contains(secret) {
+                msg = msg.replace(secret, MASK);
+            }
+        }
+        msg
+    }
+
+    /// Writes masked message to `to`, prefixing each line
+    fn write_lines(&self, to: &mut impl Write, msg: impl Display) {
+        let msg = self.mask(msg);
+        for line in msg.trim_end_matches('\n').split('\n') {
+            // output of the action is not worth failing it
+            let _ = writeln!(to, "{} {}", &self.prefix, line);
+        }
+    }
+
+    pub fn out(&self, msg: impl Display) {
+        self.write_lines(&mut io::stdout(), msg);
+    }
+
+    pub fn err(&self, msg: impl Display) {
+        self.write_lines(&mut io::stderr(), msg);
+    }
+
+    fn forward(&self, stream: impl Read, to: &mut impl Write) {
+        for line in BufReader::new(stream).split(b'\n') {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            let line = String::from_utf8_lossy(&line);
+            self.write_lines(to, line.trim_end_matches('\r'));
+        }
+    }
+
+    /// Runs command, forwarding its stdout and stderr line by line
+    pub fn run(&self, cmd: &mut Command) -> io::Result<ExitStatus> {
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        std::thread::scope(|s| {
+            s.spawn(|| self.forward(stdout, &mut io::stdout()));
+            s.spawn(|| self.forward(stderr, &mut io::stderr()));
+        });
+        child.wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forward(log: &Logger, input: &str) -> String {
+        let mut out = Vec::new();
+        log.forward(input.as_bytes(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn forwarded_output_is_masked() {
+        let log = Logger::new("act", vec!["hunter2"].into_iter());
+        assert_eq!(
+            forward(&log, "password is hunter2\r\nhunter2hunter2\nclean\n"),
+            "[act] password is ***\n[act] ******\n[act] clean\n"
+        );
+    }
+
+    #[test]
+    fn stdout_and_stderr_are_masked() {
+        let log = Logger::new("act", vec!["hunter2"].into_iter());
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo out hunter2; echo err hunter2 >&2"]);
+        let mut child = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        log.forward(child.stdout.take().unwrap(), &mut out);
+        log.forward(child.stderr.take().unwrap(), &mut err);
+        assert!(child.wait().unwrap().success());
+        assert_eq!(String::from_utf8(out).unwrap(), "[act] out ***\n");
+        assert_eq!(String::from_utf8(err).unwrap(), "[act] err ***\n");
+    }
+
+    #[test]
+    fn multi_line_secret() {
+        let log = Logger::new("act", vec!["-----BEGIN KEY-----\n  abcdef\n\n"].into_iter());
+        assert_eq!(
+            forward(&log, "-----BEGIN KEY-----\nabcdef\nabc\n"),
+            "[act] ***\n[act] ***\n[act] abc\n"
+        );
+    }
+
+    #[test]
+    fn longest_secret_is_masked_first() {
+        let log = Logger::new("act", vec!["abc", "abcdef", "cde"].into_iter());
+        assert_eq!(forward(&log, "abcdef abcd\n"), "[act] *** ***d\n");
+    }
+}
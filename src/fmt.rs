@@ -0,0 +1,304 @@
+//! Canonical layout of main.workflow files.
+//!
+//! Blocks keep their order, keys are sorted in fixed order and `=` signs are aligned.
+//! Comments stay attached to the definition or key they precede.
+use crate::hir::parser::{Block, BlockKind, Comments, Document, Entry, Value};
+
+const INDENT: &str = "  ";
+
+/// Arrays, which make line longer than this, are written one item per line
+const MAX_WIDTH: usize = 80;
+
+const WORKFLOW_KEYS: &[&str] = &["on", "resolves"];
+const ACTION_KEYS: &[&str] = &["uses", "needs", "runs", "args", "env", "secrets"];
+
+fn key_rank(kind: BlockKind, key: &str) -> usize {
+    let keys = match kind {
+        BlockKind::Workflow => WORKFLOW_KEYS,
+        BlockKind::Action => ACTION_KEYS,
+    };
+    // unknown keys go last, keeping their relative order
+    keys.iter().position(|&k| k == key).unwrap_or(keys.len())
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            ch if ch.is_control() => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Writes string as heredoc if it consists of several lines, otherwise quotes it
+fn literal(s: &str) -> String {
+    let lines: Vec<_> = match s.strip_suffix('\n') {
+        Some(body) if body.contains('\n') && !s.contains('\r') => body.split('\n').collect(),
+        _ => return quote(s),
+    };
+    let mut marker = String::from("EOF");
+    let mut suffix = 1;
+    // any line starting with marker could be taken for the end of heredoc
+    while lines.iter().any(|line| line.trim().starts_with(&marker)) {
+        marker = format!("EOF{}", suffix);
+        suffix += 1;
+    }
+    format!("<<{}\n{}{}", marker, s, marker)
+}
+
+fn push_comments(out: &mut String, indent: &str, comments: &[String]) {
+    for comment in comments {
+        out.push_str(indent);
+        out.push_str(comment);
+        out.push('\n');
+    }
+}
+
+/// Heredoc ends with line, consisting only of its marker, so trailing comment
+/// of item with heredoc value is moved to lines before the item
+fn hoist_trailing(comments: &Comments, value: &str) -> Comments {
+    let mut comments = comments.clone();
+    if value.starts_with("<<") {
+        comments.before.extend(comments.trailing.take());
+    }
+    comments
+}
+
+/// Finishes line, adding trailing comment if any
+fn end_line(out: &mut String, comments: &Comments) {
+    if let Some(comment) = &comments.trailing {
+        out.push(' ');
+        out.push_str(comment);
+    }
+    out.push('\n');
+}
+
+/// Renders value of the entry, starting at the end of `prefix`
+fn format_value(prefix: &str, entry: &Entry) -> String {
+    match &entry.value {
+        Value::String(s) => literal(s),
+        Value::Array(items) => {
+            // heredoc must end the line, so it can't be followed by `,`
+            let items: Vec<_> = items.iter().map(|s| quote(s)).collect();
+            let inline = format!("[{}]", items.join(", "));
+            if prefix.len() + inline.len() <= MAX_WIDTH {
+                return inline;
+            }
+            let mut out = String::from("[\n");
+            out.push_str(
+                &items
+                    .iter()
+                    .map(|item| format!("{}{}{}", INDENT, INDENT, item))
+                    .collect::<Vec<_>>()
+                    .join(",\n"),
+            );
+            out.push('\n');
+            out.push_str(INDENT);
+            out.push(']');
+            out
+        }
+        Value::Map(map) if map.is_empty() => "{}".to_string(),
+        Value::Map(map) => {
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            let width = keys.iter().map(|k| k.len()).max().unwrap_or(0);
+            let indent = INDENT.repeat(2);
+            let no_comments = Comments::default();
+            let mut out = String::from("{\n");
+            for key in keys {
+                let value = literal(&map[key]);
+                let comments =
+                    hoist_trailing(entry.map_comments.get(key).unwrap_or(&no_comments), &value);
+                push_comments(&mut out, &indent, &comments.before);
+                out.push_str(&format!(
+                    "{}{:width$} = {}",
+                    indent,
+                    key,
+                    value,
+                    width = width
+                ));
+                end_line(&mut out, &comments);
+            }
+            out.push_str(INDENT);
+            out.push('}');
+            out
+        }
+    }
+}
+
+fn format_entry(out: &mut String, prefix: &str, entry: &Entry) {
+    let value = format_value(prefix, entry);
+    let comments = hoist_trailing(&entry.comments, &value);
+    push_comments(out, INDENT, &comments.before);
+    out.push_str(prefix);
+    out.push_str(&value);
+    end_line(out, &comments);
+}
+
+fn format_block(out: &mut String, block: &Block) {
+    push_comments(out, "", &block.comments.before);
+    out.push_str(&format!(
+        "{} {} {{",
+        block.kind.keyword(),
+        quote(&block.name)
+    ));
+    end_line(out, &block.comments);
+    let mut entries: Vec<_> = block.entries.iter().collect();
+    entries.sort_by_key(|entry| key_rank(block.kind, &entry.key));
+    let width = entries.iter().map(|e| e.key.len()).max().unwrap_or(0);
+    for entry in entries {
+        let prefix = format!("{}{:width$} = ", INDENT, entry.key, width = width);
+        format_entry(out, &prefix, entry);
+    }
+    push_comments(out, INDENT, &block.end_comments);
+    out.push_str("}\n");
+}
+
+/// Renders document in canonical layout
+pub fn format(doc: &Document) -> String {
+    let mut out = String::new();
+    for (i, block) in doc.blocks.iter().enumerate() {
+        if i != 0 {
+            out.push('\n');
+        }
+        format_block(&mut out, block);
+    }
+    if !doc.end_comments.is_empty() {
+        if !doc.blocks.is_empty() {
+            out.push('\n');
+        }
+        push_comments(&mut out, "", &doc.end_comments);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::{
+        diag::Source,
+        parser::{self, Def},
+    };
+
+    const WORKFLOW: &str = r#"action "a" {
+  uses = "docker://alpine"
+  runs = ["sh", "-c", "echo start\nEOF done\n  EOF\necho end\n"]
+  env = {
+    SCRIPT = <<END
+EOF-x
+EOF.
+EOF1
+END
+  }
+}
+"#;
+
+    fn action(src: &str) -> parser::ActionDef {
+        match parser::parse(&Source::new("main.workflow", src.to_string()))
+            .unwrap()
+            .pop()
+            .unwrap()
+        {
+            Def::Action(act) => act,
+            Def::Workflow(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn marker_is_not_a_line_prefix() {
+        assert_eq!(literal("a\nEOF done\n"), "<<EOF1\na\nEOF done\nEOF1");
+        assert_eq!(
+            literal("EOF\n EOF1.\nEOF2\n"),
+            "<<EOF3\nEOF\n EOF1.\nEOF2\nEOF3"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let formatted = check_round_trip(WORKFLOW);
+        assert_eq!(action(&formatted).env["SCRIPT"], "EOF-x\nEOF.\nEOF1\n");
+    }
+
+    /// Formats `src`, checks that result parses into the same action and is formatted stably
+    fn check_round_trip(src: &str) -> String {
+        let doc = parser::parse_blocks(&Source::new("main.workflow", src.to_string())).unwrap();
+        let formatted = format(&doc);
+        let (before, after) = (action(src), action(&formatted));
+        assert_eq!(before.runs, after.runs);
+        assert_eq!(before.args, after.args);
+        assert_eq!(before.env, after.env);
+
+        let doc = parser::parse_blocks(&Source::new("main.workflow", formatted.clone())).unwrap();
+        assert_eq!(format(&doc), formatted);
+        formatted
+    }
+
+    #[test]
+    fn no_heredoc_in_array() {
+        let src = r#"action "a" {
+  uses = "docker://alpine"
+  runs = ["sh", "-c", <<EOF
+echo one
+echo two
+EOF
+  ]
+  args = [<<EOF
+first
+second
+EOF
+  , "x"]
+}
+"#;
+        let formatted = check_round_trip(src);
+        assert!(!formatted.contains("<<"), "{}", formatted);
+        assert!(
+            formatted.contains(r#"runs = ["sh", "-c", "echo one\necho two\n"]"#),
+            "{}",
+            formatted
+        );
+    }
+
+    #[test]
+    fn trailing_comment_of_heredoc_is_moved_above() {
+        let src = r#"action "a" { # header
+  uses = "docker://alpine" # image
+  args = "line 1\nline 2\n" // args
+  env = {
+    # script
+    SCRIPT = "echo one\necho two\n" /* script */
+    X = "1" # x
+  } # env
+}
+"#;
+        let formatted = check_round_trip(src);
+        assert_eq!(
+            formatted,
+            r#"action "a" { # header
+  uses = "docker://alpine" # image
+  // args
+  args = <<EOF
+line 1
+line 2
+EOF
+  env  = {
+    # script
+    /* script */
+    SCRIPT = <<EOF
+echo one
+echo two
+EOF
+    X      = "1" # x
+  } # env
+}
+"#
+        );
+    }
+}
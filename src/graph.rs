@@ -0,0 +1,300 @@
+//! Renders workflows and actions with their dependencies as a graph.
+//!
+//! Workflows point to actions they resolve, and actions point to actions they need.
+use crate::{
+    hir::{ActionId, Hir},
+    scheduler,
+};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Graphviz
+    Dot,
+    Mermaid,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "mermaid" => Ok(Format::Mermaid),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown graph format {}, expected dot, mermaid or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Actions, which would run for chosen targets
+pub struct Highlight {
+    actions: Vec<bool>,
+}
+
+impl Highlight {
+    /// Highlights target actions and all actions they need
+    pub fn new(h: &Hir, targets: &[String]) -> Result<Highlight, String> {
+        let roots = targets
+            .iter()
+            .map(|name| {
+                h.find_action(name)
+                    .ok_or_else(|| format!("action '{}' is not defined", name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Highlight {
+            actions: scheduler::Graph::new(h).closure(&roots),
+        })
+    }
+
+    fn contains(&self, act: ActionId) -> bool {
+        self.actions[act.as_inner()]
+    }
+}
+
+/// Edge of the graph, named by node ids
+struct Edge {
+    from: String,
+    to: String,
+}
+
+fn edges(h: &Hir) -> Vec<Edge> {
+    let g = scheduler::Graph::new(h);
+    let mut edges = vec![];
+    for (i, wf) in h.workflows().enumerate() {
+        for dep in &wf.depends {
+            edges.push(Edge {
+                from: format!("w{}", i),
+                to: format!("a{}", dep.as_inner()),
+            });
+        }
+    }
+    for i in 0..h.actions_cnt() {
+        for dep in g.needs(ActionId::new(i)) {
+            edges.push(Edge {
+                from: format!("a{}", i),
+                to: format!("a{}", dep.as_inner()),
+            });
+        }
+    }
+    edges
+}
+
+fn is_highlighted(highlight: Option<&Highlight>, act: usize) -> bool {
+    highlight.is_some_and(|hl| hl.contains(ActionId::new(act)))
+}
+
+/// Escapes string for use inside of double quotes
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn dot(h: &Hir, highlight: Option<&Highlight>) -> String {
+    let mut out = String::from("digraph workflows {\n");
+    for (i, wf) in h.workflows().enumerate() {
+        out.push_str(&format!(
+            "  w{} [label=\"{}\", shape=box];\n",
+            i,
+            escape_dot(&wf.name)
+        ));
+    }
+    for (i, act) in h.actions().enumerate() {
+        let style = if is_highlighted(highlight, i) {
+            ", style=filled, fillcolor=gold"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "  a{} [label=\"{}\"{}];\n",
+            i,
+            escape_dot(&act.name),
+            style
+        ));
+    }
+    for edge in edges(h) {
+        out.push_str(&format!("  {} -> {};\n", edge.from, edge.to));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Mermaid labels can't contain quotes, so they are replaced with entity codes
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;").replace('\n', " ")
+}
+
+fn mermaid(h: &Hir, highlight: Option<&Highlight>) -> String {
+    let mut out = String::from("flowchart TD\n");
+    for (i, wf) in h.workflows().enumerate() {
+        out.push_str(&format!("  w{}[[\"{}\"]]\n", i, escape_mermaid(&wf.name)));
+    }
+    for (i, act) in h.actions().enumerate() {
+        out.push_str(&format!("  a{}(\"{}\")\n", i, escape_mermaid(&act.name)));
+    }
+    for edge in edges(h) {
+        out.push_str(&format!("  {} --> {}\n", edge.from, edge.to));
+    }
+    let highlighted: Vec<_> = (0..h.actions_cnt())
+        .filter(|&i| is_highlighted(highlight, i))
+        .map(|i| format!("a{}", i))
+        .collect();
+    if !highlighted.is_empty() {
+        out.push_str("  classDef target fill:#ffd700\n");
+        out.push_str(&format!("  class {} target\n", highlighted.join(",")));
+    }
+    out
+}
+
+fn json(h: &Hir, highlight: Option<&Highlight>) -> String {
+    let names = |ids: &[ActionId]| -> Vec<&str> {
+        ids.iter().map(|&dep| h.action(dep).name.as_str()).collect()
+    };
+    let workflows: Vec<_> = h
+        .workflows()
+        .map(|wf| {
+            let on: Vec<_> = wf.on.iter().map(ToString::to_string).collect();
+            serde_json::json!({
+                "name": &wf.name,
+                "on": on,
+                "resolves": names(&wf.depends),
+            })
+        })
+        .collect();
+    let actions: Vec<_> = h
+        .actions()
+        .enumerate()
+        .map(|(i, act)| {
+            let mut val = serde_json::json!({
+                "name": &act.name,
+                "uses": &act.uses,
+                "needs": names(&act.needs),
+            });
+            if highlight.is_some() {
+                val["highlighted"] = is_highlighted(highlight, i).into();
+            }
+            val
+        })
+        .collect();
+    let graph = serde_json::json!({
+        "workflows": workflows,
+        "actions": actions,
+    });
+    let mut out = serde_json::to_string_pretty(&graph).expect("failed serialize graph");
+    out.push('\n');
+    out
+}
+
+/// Renders graph in given format, marking highlighted actions if any
+pub fn render(h: &Hir, format: Format, highlight: Option<&Highlight>) -> String {
+    match format {
+        Format::Dot => dot(h, highlight),
+        Format::Mermaid => mermaid(h, highlight),
+        Format::Json => json(h, highlight),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::{self, diag::Source, parser};
+
+    const WORKFLOW: &str = r#"
+workflow "say \"hi\"" {
+  on = "push"
+  resolves = ["test"]
+}
+
+action "build\\n" {
+  uses = "docker://rust"
+}
+
+action "test" {
+  uses = "docker://rust"
+  needs = ["build\\n"]
+}
+
+action "lint \"all\"\nnow" {
+  uses = "docker://rust"
+}
+"#;
+
+    fn load() -> Hir {
+        let defs = parser::parse(&Source::new("main.workflow", WORKFLOW.to_string())).unwrap();
+        hir::build(defs).unwrap()
+    }
+
+    #[test]
+    fn dot() {
+        let h = load();
+        let hl = Highlight::new(&h, &["test".to_string()]).unwrap();
+        assert_eq!(
+            render(&h, Format::Dot, Some(&hl)),
+            r#"digraph workflows {
+  w0 [label="say \"hi\"", shape=box];
+  a0 [label="build\\n", style=filled, fillcolor=gold];
+  a1 [label="test", style=filled, fillcolor=gold];
+  a2 [label="lint \"all\"\nnow"];
+  w0 -> a1;
+  a1 -> a0;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn mermaid() {
+        let h = load();
+        let hl = Highlight::new(&h, &["test".to_string()]).unwrap();
+        assert_eq!(
+            render(&h, Format::Mermaid, Some(&hl)),
+            r#"flowchart TD
+  w0[["say #quot;hi#quot;"]]
+  a0("build\n")
+  a1("test")
+  a2("lint #quot;all#quot; now")
+  w0 --> a1
+  a1 --> a0
+  classDef target fill:#ffd700
+  class a0,a1 target
+"#
+        );
+        assert!(!render(&h, Format::Mermaid, None).contains("classDef"));
+    }
+
+    #[test]
+    fn json() {
+        let h = load();
+        let hl = Highlight::new(&h, &["lint \"all\"\nnow".to_string()]).unwrap();
+        let graph: serde_json::Value =
+            serde_json::from_str(&render(&h, Format::Json, Some(&hl))).unwrap();
+        assert_eq!(
+            graph,
+            serde_json::json!({
+                "workflows": [
+                    {"name": "say \"hi\"", "on": ["push"], "resolves": ["test"]},
+                ],
+                "actions": [
+                    {"name": "build\\n", "uses": "docker://rust", "needs": [], "highlighted": false},
+                    {"name": "test", "uses": "docker://rust", "needs": ["build\\n"], "highlighted": false},
+                    {"name": "lint \"all\"\nnow", "uses": "docker://rust", "needs": [], "highlighted": true},
+                ],
+            })
+        );
+        let graph: serde_json::Value =
+            serde_json::from_str(&render(&h, Format::Json, None)).unwrap();
+        assert!(graph["actions"][0].get("highlighted").is_none());
+    }
+
+    #[test]
+    fn unknown_target() {
+        let h = load();
+        let err = Highlight::new(&h, &["deploy".to_string()]).err().unwrap();
+        assert_eq!(err, "action 'deploy' is not defined");
+    }
+}
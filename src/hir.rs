@@ -1,7 +1,10 @@
+pub mod cron;
+pub mod diag;
 mod lint;
 pub mod parser;
+pub mod yaml;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub struct ActionId(usize);
@@ -18,14 +21,149 @@ impl ActionId {
 
 #[derive(Debug, Clone)]
 pub enum Event {
+    CheckRun,
+    CheckSuite,
+    CommitComment,
+    Create,
+    Delete,
+    Deployment,
+    DeploymentStatus,
+    Fork,
+    Gollum,
+    IssueComment,
+    Issues,
+    Label,
+    Member,
+    Milestone,
+    PageBuild,
+    Project,
+    ProjectCard,
+    ProjectColumn,
+    Public,
+    PullRequest,
+    PullRequestReview,
+    PullRequestReviewComment,
+    /// Only available in YAML workflows
+    PullRequestTarget,
     Push,
+    Release,
+    RepositoryDispatch,
+    RepositoryVulnerabilityAlert,
+    Status,
+    Watch,
+    /// Only available in YAML workflows
+    WorkflowDispatch,
+    /// `schedule(<cron expression>)`
+    Schedule(cron::Schedule),
+}
+
+/// Events, which are triggered by name only
+const SIMPLE_EVENTS: &[(&str, Event)] = &[
+    ("check_run", Event::CheckRun),
+    ("check_suite", Event::CheckSuite),
+    ("commit_comment", Event::CommitComment),
+    ("create", Event::Create),
+    ("delete", Event::Delete),
+    ("deployment", Event::Deployment),
+    ("deployment_status", Event::DeploymentStatus),
+    ("fork", Event::Fork),
+    ("gollum", Event::Gollum),
+    ("issue_comment", Event::IssueComment),
+    ("issues", Event::Issues),
+    ("label", Event::Label),
+    ("member", Event::Member),
+    ("milestone", Event::Milestone),
+    ("page_build", Event::PageBuild),
+    ("project", Event::Project),
+    ("project_card", Event::ProjectCard),
+    ("project_column", Event::ProjectColumn),
+    ("public", Event::Public),
+    ("pull_request", Event::PullRequest),
+    ("pull_request_review", Event::PullRequestReview),
+    ("pull_request_review_comment", Event::PullRequestReviewComment),
+    ("push", Event::Push),
+    ("release", Event::Release),
+    ("repository_dispatch", Event::RepositoryDispatch),
+    ("repository_vulnerability_alert", Event::RepositoryVulnerabilityAlert),
+    ("status", Event::Status),
+    ("watch", Event::Watch),
+];
+
+/// Events, which can only trigger YAML workflows
+const YAML_EVENTS: &[(&str, Event)] = &[
+    ("pull_request_target", Event::PullRequestTarget),
+    ("workflow_dispatch", Event::WorkflowDispatch),
+];
+
+impl Event {
+    /// Parses value of workflow `on` key
+    pub fn parse(s: &str) -> Result<Event, String> {
+        if let Some((_, ev)) = SIMPLE_EVENTS.iter().find(|(name, _)| *name == s) {
+            return Ok(ev.clone());
+        }
+        if YAML_EVENTS.iter().any(|(name, _)| *name == s) {
+            return Err(format!(
+                "workflow trigger {} is only available in YAML workflows",
+                s
+            ));
+        }
+        if s.starts_with("schedule(") && s.ends_with(')') {
+            let expr = &s["schedule(".len()..s.len() - 1];
+            return cron::Schedule::parse(expr)
+                .map(Event::Schedule)
+                .map_err(|err| format!("invalid schedule {}: {}", s, err));
+        }
+        Err(format!("unknown workflow trigger: {}", s))
+    }
+
+    /// Parses trigger of YAML workflow, lowered into format of `on` key
+    pub fn parse_yaml(s: &str) -> Result<Event, String> {
+        match YAML_EVENTS.iter().find(|(name, _)| *name == s) {
+            Some((_, ev)) => Ok(ev.clone()),
+            None => Event::parse(s),
+        }
+    }
+
+    /// Checks that `name` can be used as `GITHUB_EVENT_NAME`
+    pub fn is_known_name(name: &str) -> bool {
+        name == "schedule"
+            || SIMPLE_EVENTS
+                .iter()
+                .chain(YAML_EVENTS)
+                .any(|(n, _)| *n == name)
+    }
+
+    /// Returns event name, as used in `GITHUB_EVENT_NAME`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Schedule(_) => "schedule",
+            ev => SIMPLE_EVENTS
+                .iter()
+                .chain(YAML_EVENTS)
+                .find(|(_, e)| std::mem::discriminant(e) == std::mem::discriminant(ev))
+                .map(|(name, _)| *name)
+                .expect("all simple events are listed"),
+        }
+    }
+}
+
+impl std::fmt::Display for Event {
+    /// Formats event as value of `on` key
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Event::Schedule(s) => write!(f, "schedule({})", s),
+            ev => f.write_str(ev.name()),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Workflow {
     pub name: String,
-    pub on: Event,
+    /// Events, triggering the workflow
+    pub on: Vec<Event>,
     pub depends: Vec<ActionId>,
+    pub spans: diag::DefSpans,
 }
 
 #[derive(Clone, Debug)]
@@ -37,22 +175,40 @@ pub struct Action {
     pub needs: Vec<ActionId>,
     pub env: HashMap<String, String>,
     pub secrets: Vec<String>,
+    pub spans: diag::DefSpans,
 }
 
 /// Top-level struct
 #[derive(Clone, Debug)]
 pub struct Hir {
-    workflow: Workflow,
+    workflows: Vec<Workflow>,
     actions: Vec<Action>,
 }
 
+impl Workflow {
+    pub fn is_triggered_by(&self, event_name: &str) -> bool {
+        self.on.iter().any(|ev| ev.name() == event_name)
+    }
+}
+
 impl Hir {
     pub fn action(&self, id: ActionId) -> &Action {
         &self.actions[id.0]
     }
 
-    pub fn workflow(&self) -> &Workflow {
-        &self.workflow
+    pub fn workflows(&self) -> impl Iterator<Item = &Workflow> {
+        self.workflows.iter()
+    }
+
+    pub fn find_workflow(&self, name: &str) -> Option<&Workflow> {
+        self.workflows.iter().find(|wf| wf.name == name)
+    }
+
+    pub fn find_action(&self, name: &str) -> Option<ActionId> {
+        self.actions
+            .iter()
+            .position(|act| act.name == name)
+            .map(ActionId)
     }
 
     pub fn actions(&self) -> impl Iterator<Item = &Action> {
@@ -65,8 +221,12 @@ impl Hir {
 }
 
 mod builder {
-    use super::{parser::Def, Action, ActionId, Event, Hir, Workflow};
-    use std::{collections::HashMap, process::exit};
+    use super::{
+        diag::{Diagnostic, Diagnostics},
+        parser::Def,
+        Action, ActionId, Event, Hir, Workflow,
+    };
+    use std::collections::{HashMap, HashSet};
 
     #[derive(Default)]
     struct ActionIdMapper {
@@ -94,54 +254,74 @@ mod builder {
         }
     }
 
-    fn generate_hir(defs: &[Def]) -> Hir {
+    fn generate_hir(defs: &[Def], diags: &mut Diagnostics) -> Hir {
         let mut actions = vec![];
-        let mut workflow = None;
+        let mut workflows: Vec<Workflow> = vec![];
         let mut action_id_mapper = ActionIdMapper::new();
         for def in defs {
             if let Def::Action(def) = def {
                 if !action_id_mapper.feed(&def.name).0 {
-                    eprintln!("action with name {} already defined", def.name);
-                    exit(1);
+                    diags.push(Diagnostic::at(
+                        def.spans.def.clone(),
+                        format!("action with name {} already defined", def.name),
+                    ));
                 }
             }
         }
+        let mut seen_actions = HashSet::new();
         for def in defs {
             match def {
                 Def::Workflow(def) => {
-                    if workflow.is_some() {
-                        eprintln!("multiple workflows are not supported");
-                        exit(1);
+                    if workflows.iter().any(|wf| wf.name == def.name) {
+                        diags.push(Diagnostic::at(
+                            def.spans.def.clone(),
+                            format!("workflow with name {} already defined", def.name),
+                        ));
+                        continue;
                     }
-                    let on = match def.on.as_str() {
-                        "push" => Event::Push,
-                        _ => {
-                            eprintln!("unknown workflow trigger: {}", &def.on);
-                            exit(1);
-                        }
+                    let mut on = vec![];
+                    let parse = if def.yaml {
+                        Event::parse_yaml
+                    } else {
+                        Event::parse
                     };
+                    for ev in &def.on {
+                        match parse(ev) {
+                            Ok(ev) => on.push(ev),
+                            Err(err) => diags.push(Diagnostic::at(def.spans.key("on"), err)),
+                        }
+                    }
                     let mut depends = vec![];
                     for dep in &def.resolves {
-                        let dep_id = action_id_mapper.get(dep).unwrap_or_else(|| {
-                            eprintln!("workflow refers to unknown action {}", dep);
-                            exit(1);
-                        });
-                        depends.push(dep_id);
+                        match action_id_mapper.get(dep) {
+                            Some(dep_id) => depends.push(dep_id),
+                            None => diags.push(Diagnostic::at(
+                                def.spans.key("resolves"),
+                                format!("workflow refers to unknown action {}", dep),
+                            )),
+                        }
                     }
-                    workflow = Some(Workflow {
+                    workflows.push(Workflow {
                         name: def.name.clone(),
                         on,
                         depends,
+                        spans: def.spans.clone(),
                     });
                 }
                 Def::Action(def) => {
+                    // duplicates are already reported
+                    if !seen_actions.insert(def.name.as_str()) {
+                        continue;
+                    }
                     let mut depends = vec![];
                     for dep in &def.needs {
-                        let dep_id = action_id_mapper.get(dep).unwrap_or_else(|| {
-                            eprintln!("action {} refers to unknown action {}", def.name, dep);
-                            exit(1);
-                        });
-                        depends.push(dep_id);
+                        match action_id_mapper.get(dep) {
+                            Some(dep_id) => depends.push(dep_id),
+                            None => diags.push(Diagnostic::at(
+                                def.spans.key("needs"),
+                                format!("action {} refers to unknown action {}", def.name, dep),
+                            )),
+                        }
                     }
                     actions.push(Action {
                         name: def.name.clone(),
@@ -151,25 +331,350 @@ mod builder {
                         needs: depends,
                         env: def.env.clone(),
                         secrets: def.secrets.clone(),
+                        spans: def.spans.clone(),
                     })
                 }
             }
         }
-        let workflow = workflow.unwrap_or_else(|| {
-            eprintln!("no workflows found");
-            exit(1);
-        });
+        if workflows.is_empty() {
+            diags.push(Diagnostic::new("no workflows found"));
+        }
 
-        Hir { workflow, actions }
+        Hir { workflows, actions }
     }
 
-    pub fn build(defs: Vec<Def>) -> Hir {
-        generate_hir(&defs)
+    /// Builds Hir, skipping erroneous parts of definitions and adding errors to `diags`
+    pub fn build(defs: Vec<Def>, diags: &mut Diagnostics) -> Hir {
+        generate_hir(&defs, diags)
     }
 }
 
-pub fn build(defs: Vec<parser::Def>) -> Hir {
-    let h = builder::build(defs);
-    lint::lint(&h);
+/// Builds Hir and lints it, adding all found problems to `diags`
+pub fn build_partial(defs: Vec<parser::Def>, diags: &mut diag::Diagnostics) -> Hir {
+    let h = builder::build(defs, diags);
+    diags.append(lint::lint(&h));
     h
 }
+
+/// YAML workflow, which can't be run locally
+pub struct Disabled {
+    pub name: String,
+    /// Path of the workflow file
+    pub path: String,
+    /// Problems, which disabled the workflow
+    pub diags: diag::Diagnostics,
+}
+
+/// Workflows of the repository
+pub struct Loaded {
+    pub hir: Hir,
+    /// YAML workflows, which could not be lowered or built.
+    /// They are excluded from `hir`, so that other workflows still can be used.
+    pub disabled: Vec<Disabled>,
+    /// Constructs of YAML workflows, which are lowered not exactly
+    pub warnings: diag::Diagnostics,
+}
+
+/// YAML workflow file and its definitions, if it was lowered successfully
+struct YamlFile {
+    name: String,
+    path: String,
+    defs: Result<Vec<parser::Def>, diag::Diagnostics>,
+}
+
+/// Builds `hcl` definitions with definitions of YAML files, which are not disabled yet.
+/// Problems in YAML files disable them, and the rest is built again without them.
+fn build_loaded(
+    hcl: Vec<parser::Def>,
+    yaml: &mut [YamlFile],
+    diags: &mut diag::Diagnostics,
+) -> Hir {
+    loop {
+        let mut defs = hcl.clone();
+        for file in yaml.iter() {
+            if let Ok(file_defs) = &file.defs {
+                defs.extend(file_defs.iter().cloned());
+            }
+        }
+        let mut build_diags = diag::Diagnostics::new();
+        let h = build_partial(defs, &mut build_diags);
+        let mut rest = diag::Diagnostics::new();
+        let mut disabled_any = false;
+        for d in build_diags {
+            match yaml
+                .iter_mut()
+                .find(|file| d.path() == Some(file.path.as_str()))
+            {
+                Some(file) => {
+                    if let Err(file_diags) = &mut file.defs {
+                        file_diags.push(d);
+                    } else {
+                        file.defs = Err(d.into());
+                    }
+                    disabled_any = true;
+                }
+                None => rest.push(d),
+            }
+        }
+        if !disabled_any {
+            diags.append(rest);
+            return h;
+        }
+    }
+}
+
+/// Loads `.github/main.workflow` and `.github/workflows/*.yml` of the repository.
+///
+/// Problems of main.workflow and of the workflows it defines are reported together.
+/// YAML workflows with problems are disabled one by one.
+pub fn load(repo: &Path) -> Result<Loaded, diag::Diagnostics> {
+    let mut hcl = vec![];
+    let mut yaml = vec![];
+    let mut diags = diag::Diagnostics::new();
+    let mut warnings = diag::Diagnostics::new();
+    // definitions of unreadable or syntactically invalid files are missing,
+    // so building the rest would report bogus unknown actions
+    let mut complete = true;
+    let mut found = false;
+    let hcl_path = repo.join(".github/main.workflow");
+    if hcl_path.exists() {
+        match diag::Source::read(&hcl_path) {
+            Ok(source) => match parser::parse_partial(&source, &mut diags) {
+                Some(parsed) => hcl.extend(parsed),
+                None => complete = false,
+            },
+            Err(err) => {
+                diags.push(err);
+                complete = false;
+            }
+        }
+        found = true;
+    }
+    let yaml_dir = repo.join(".github/workflows");
+    if yaml_dir.is_dir() {
+        let mut paths = vec![];
+        let entries = std::fs::read_dir(&yaml_dir).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()
+        });
+        match entries {
+            Ok(entries) => paths.extend(entries.into_iter().filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "yml" || ext == "yaml")
+            })),
+            Err(err) => {
+                diags.push(diag::Diagnostic::new(format!(
+                    "failed list {}: {}",
+                    yaml_dir.display(),
+                    err
+                )));
+                complete = false;
+            }
+        }
+        paths.sort();
+        for path in paths {
+            match diag::Source::read(&path) {
+                Ok(source) => yaml.push(YamlFile {
+                    name: yaml::workflow_name(&source),
+                    path: source.path.clone(),
+                    defs: yaml::parse(&source, &mut warnings),
+                }),
+                Err(err) => {
+                    diags.push(err);
+                    complete = false;
+                }
+            }
+            found = true;
+        }
+    }
+    if !found {
+        diags.push(diag::Diagnostic::new(format!(
+            "neither {} nor {}/*.yml exist",
+            hcl_path.display(),
+            yaml_dir.display()
+        )));
+    }
+    if !found || !complete {
+        return Err(diags);
+    }
+    let hir = build_loaded(hcl, &mut yaml, &mut diags);
+    let disabled = yaml.into_iter().filter_map(|file| match file.defs {
+        Ok(_) => None,
+        Err(file_diags) => Some(Disabled {
+            name: file.name,
+            path: file.path,
+            diags: file_diags,
+        }),
+    });
+    if !diags.is_empty() {
+        // reasons of disabling may explain other errors, e.g. that no workflows are found
+        for file in disabled {
+            diags.append(file.diags);
+        }
+        return Err(diags);
+    }
+    Ok(Loaded {
+        hir,
+        disabled: disabled.collect(),
+        warnings,
+    })
+}
+
+pub fn build(defs: Vec<parser::Def>) -> Result<Hir, diag::Diagnostics> {
+    let mut diags = diag::Diagnostics::new();
+    let h = build_partial(defs, &mut diags);
+    diags.finish(h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Repository with given main.workflow
+    fn repo(hcl: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".github")).unwrap();
+        std::fs::write(dir.path().join(".github/main.workflow"), hcl).unwrap();
+        dir
+    }
+
+    #[test]
+    fn all_problems_are_reported_at_once() {
+        let dir = repo(
+            r#"workflow "ci" {
+  on = "pushed"
+  resolves = ["a", "missing"]
+}
+
+action "a" {
+  uses = "docker://alpine"
+  needs = ["b"]
+  runs = []
+  unknown = "x"
+  env = {
+    GITHUB_X = "1"
+  }
+}
+"#,
+        );
+        let diags = load(dir.path()).err().unwrap();
+        assert_eq!(diags.len(), 6, "{}", diags);
+        let text = diags.to_string();
+        // every problem points at the offending line
+        for (location, message) in &[
+            ("main.workflow:10:3", "unexpected key unknown"),
+            ("main.workflow:2:3", "unknown workflow trigger: pushed"),
+            (
+                "main.workflow:3:3",
+                "workflow refers to unknown action missing",
+            ),
+            ("main.workflow:8:3", "action a refers to unknown action b"),
+            (
+                "main.workflow:12:5",
+                "in env var 'GITHUB_X': var name starts with reserved prefix GITHUB_",
+            ),
+            ("main.workflow:9:3", "runs is defined to empty value"),
+        ] {
+            assert!(text.contains(location), "{}", text);
+            assert!(text.contains(message), "{}", text);
+        }
+        assert!(text.contains("12 |     GITHUB_X = \"1\""), "{}", text);
+    }
+
+    /// Adds YAML workflow to repository
+    fn add_yaml(dir: &tempfile::TempDir, name: &str, yaml: &str) {
+        let workflows = dir.path().join(".github/workflows");
+        std::fs::create_dir_all(&workflows).unwrap();
+        std::fs::write(workflows.join(name), yaml).unwrap();
+    }
+
+    const HCL: &str = "workflow \"ci\" {\n  on = \"push\"\n  resolves = [\"a\"]\n}\n\naction \"a\" {\n  uses = \"docker://alpine\"\n}\n";
+
+    #[test]
+    fn yaml_problems_disable_only_their_workflow() {
+        let dir = repo(HCL);
+        let steps = "    steps:\n      - run: echo hi\n";
+        add_yaml(
+            &dir,
+            "good.yml",
+            &format!("on: push\njobs:\n  a:\n    container: alpine\n{}", steps),
+        );
+        add_yaml(
+            &dir,
+            "win.yml",
+            &format!(
+                "on: push\njobs:\n  a:\n    runs-on: windows-latest\n{}",
+                steps
+            ),
+        );
+        add_yaml(
+            &dir,
+            "called.yml",
+            &format!(
+                "name: lib\non: workflow_call\njobs:\n  a:\n    container: alpine\n{}",
+                steps
+            ),
+        );
+        add_yaml(
+            &dir,
+            "reuse.yml",
+            "on: push\njobs:\n  a:\n    uses: owner/repo/.github/workflows/x.yml@v1\n",
+        );
+        add_yaml(&dir, "broken.yml", "on: [push\n");
+
+        let loaded = load(dir.path()).unwrap_or_else(|diags| panic!("{}", diags));
+        let names: Vec<_> = loaded.hir.workflows().map(|wf| wf.name.as_str()).collect();
+        assert_eq!(names, ["ci", "good"]);
+        assert!(loaded.hir.find_action("lib/a/step-1").is_none());
+
+        let disabled: Vec<_> = loaded
+            .disabled
+            .iter()
+            .map(|wf| (wf.name.as_str(), wf.diags.to_string()))
+            .collect();
+        assert_eq!(disabled.len(), 4);
+        for ((name, diags), (expected_name, expected)) in disabled.iter().zip(&[
+            ("broken", "broken.yml"),
+            ("lib", "unknown workflow trigger: workflow_call"),
+            ("reuse", "reusable workflows are not supported"),
+            ("win", "runs-on has no docker image equivalent"),
+        ]) {
+            assert_eq!(name, expected_name);
+            assert!(diags.contains(expected), "{}", diags);
+        }
+    }
+
+    #[test]
+    fn hcl_problems_are_not_disabled() {
+        let dir = repo("workflow \"ci\" {\n  on = \"push\"\n  resolves = [\"missing\"]\n}\n");
+        add_yaml(&dir, "win.yml", "on: push\njobs:\n  a:\n    runs-on: windows-latest\n    steps:\n      - run: echo hi\n");
+        let text = load(dir.path()).err().unwrap().to_string();
+        assert!(text.contains("unknown action missing"), "{}", text);
+        // reasons of disabling are reported with errors
+        assert!(
+            text.contains("runs-on has no docker image equivalent"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn syntax_error_is_reported_alone() {
+        let dir = repo("workflow \"ci\" {\n  on = \"push\"\n  resolves = [\"a\"\n}\n");
+        let diags = load(dir.path()).err().unwrap();
+        assert_eq!(diags.len(), 1, "{}", diags);
+        let text = diags.to_string();
+        assert!(text.contains("main.workflow:4:1"), "{}", text);
+        assert!(text.contains("syntax error"), "{}", text);
+    }
+
+    #[test]
+    fn build_reports_lints_with_errors() {
+        let src = "workflow \"ci\" {\n  on = \"push\"\n  resolves = [\"missing\"]\n}\n\naction \"a\" {\n  uses = \"docker://alpine\"\n  args = []\n}\n";
+        let defs = parser::parse(&diag::Source::new("main.workflow", src.to_string())).unwrap();
+        let text = build(defs).err().unwrap().to_string();
+        assert!(text.contains("unknown action missing"), "{}", text);
+        assert!(text.contains("args is defined to empty value"), "{}", text);
+    }
+}
@@ -0,0 +1,262 @@
+//! Parser for cron expressions, used in `schedule(...)` triggers
+use std::fmt;
+
+/// Set of allowed values of a single cron field, stored as bitmask
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CronField(u64);
+
+impl CronField {
+    pub fn contains(self, value: u32) -> bool {
+        value < 64 && self.0 & (1 << value) != 0
+    }
+}
+
+struct FieldSpec {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    /// Symbolic names of values, starting from `min`
+    aliases: &'static [&'static str],
+}
+
+const MONTHS: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+const FIELDS: [FieldSpec; 5] = [
+    FieldSpec {
+        name: "minute",
+        min: 0,
+        max: 59,
+        aliases: &[],
+    },
+    FieldSpec {
+        name: "hour",
+        min: 0,
+        max: 23,
+        aliases: &[],
+    },
+    FieldSpec {
+        name: "day of month",
+        min: 1,
+        max: 31,
+        aliases: &[],
+    },
+    FieldSpec {
+        name: "month",
+        min: 1,
+        max: 12,
+        aliases: MONTHS,
+    },
+    FieldSpec {
+        name: "day of week",
+        min: 0,
+        // both 0 and 7 mean Sunday
+        max: 7,
+        aliases: WEEKDAYS,
+    },
+];
+
+impl FieldSpec {
+    fn parse_value(&self, s: &str) -> Result<u32, String> {
+        let upper = s.to_ascii_uppercase();
+        if let Some(pos) = self.aliases.iter().position(|&a| a == upper) {
+            return Ok(self.min + pos as u32);
+        }
+        let val: u32 = s
+            .parse()
+            .map_err(|_| format!("invalid {} value '{}'", self.name, s))?;
+        if val < self.min || val > self.max {
+            return Err(format!(
+                "{} value {} is out of range {}-{}",
+                self.name, val, self.min, self.max
+            ));
+        }
+        Ok(val)
+    }
+
+    /// Parses single item of comma-separated list, e.g. `1-10/2`
+    fn parse_item(&self, s: &str) -> Result<u64, String> {
+        let (range, step) = match s.find('/') {
+            Some(pos) => {
+                let step = &s[pos + 1..];
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("invalid {} step '{}'", self.name, step))?;
+                if step == 0 {
+                    return Err(format!("{} step must be positive", self.name));
+                }
+                (&s[..pos], Some(step))
+            }
+            None => (s, None),
+        };
+        let (lo, hi) = if range == "*" {
+            (self.min, self.max)
+        } else if let Some(pos) = range.find('-') {
+            let lo = self.parse_value(&range[..pos])?;
+            let hi = self.parse_value(&range[pos + 1..])?;
+            if lo > hi {
+                return Err(format!("{} range {} is empty", self.name, range));
+            }
+            (lo, hi)
+        } else {
+            let val = self.parse_value(range)?;
+            // `5/15` means "starting from 5, every 15"
+            match step {
+                Some(_) => (val, self.max),
+                None => (val, val),
+            }
+        };
+        let step = step.unwrap_or(1);
+        let mut mask = 0;
+        let mut val = lo;
+        while val <= hi {
+            mask |= 1 << val;
+            val += step;
+        }
+        Ok(mask)
+    }
+
+    fn parse(&self, s: &str) -> Result<CronField, String> {
+        let mut mask = 0;
+        for item in s.split(',') {
+            mask |= self.parse_item(item)?;
+        }
+        if self.aliases == WEEKDAYS && mask & (1 << 7) != 0 {
+            mask = (mask & !(1 << 7)) | 1;
+        }
+        Ok(CronField(mask))
+    }
+}
+
+/// Parsed cron expression
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    expr: String,
+    pub minute: CronField,
+    pub hour: CronField,
+    pub day_of_month: CronField,
+    pub month: CronField,
+    pub day_of_week: CronField,
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Schedule, String> {
+        let items: Vec<&str> = expr.split_ascii_whitespace().collect();
+        if items.len() != FIELDS.len() {
+            return Err(format!(
+                "cron expression must have {} fields, got {}",
+                FIELDS.len(),
+                items.len()
+            ));
+        }
+        let mut fields = [CronField(0); 5];
+        for ((field, spec), item) in fields.iter_mut().zip(FIELDS.iter()).zip(&items) {
+            *field = spec.parse(item)?;
+        }
+        Ok(Schedule {
+            expr: items.join(" "),
+            minute: fields[0],
+            hour: fields[1],
+            day_of_month: fields[2],
+            month: fields[3],
+            day_of_week: fields[4],
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(field: CronField) -> Vec<u32> {
+        (0..64).filter(|&v| field.contains(v)).collect()
+    }
+
+    fn parse_err(expr: &str) -> String {
+        Schedule::parse(expr).unwrap_err()
+    }
+
+    #[test]
+    fn wildcards_and_values() {
+        let s = Schedule::parse("30  4 * * *").unwrap();
+        assert_eq!(values(s.minute), [30]);
+        assert_eq!(values(s.hour), [4]);
+        assert_eq!(values(s.day_of_month), (1..=31).collect::<Vec<_>>());
+        assert_eq!(values(s.month), (1..=12).collect::<Vec<_>>());
+        assert_eq!(values(s.day_of_week), (0..=6).collect::<Vec<_>>());
+        assert_eq!(s.to_string(), "30 4 * * *");
+    }
+
+    #[test]
+    fn ranges_steps_and_lists() {
+        let s = Schedule::parse("*/15 9-17 1,15 1-12/3 5/1").unwrap();
+        assert_eq!(values(s.minute), [0, 15, 30, 45]);
+        assert_eq!(values(s.hour), (9..=17).collect::<Vec<_>>());
+        assert_eq!(values(s.day_of_month), [1, 15]);
+        assert_eq!(values(s.month), [1, 4, 7, 10]);
+        // `5/1` runs up to Sunday, which is stored as 0
+        assert_eq!(values(s.day_of_week), [0, 5, 6]);
+    }
+
+    #[test]
+    fn names() {
+        let s = Schedule::parse("0 0 * jan,Jul-AUG MON-FRI").unwrap();
+        assert_eq!(values(s.month), [1, 7, 8]);
+        assert_eq!(values(s.day_of_week), [1, 2, 3, 4, 5]);
+        let s = Schedule::parse("0 0 * * SUN,7").unwrap();
+        assert_eq!(values(s.day_of_week), [0]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(
+            parse_err("60 * * * *"),
+            "minute value 60 is out of range 0-59"
+        );
+        assert_eq!(
+            parse_err("0 24 * * *"),
+            "hour value 24 is out of range 0-23"
+        );
+        assert_eq!(
+            parse_err("0 0 0 * *"),
+            "day of month value 0 is out of range 1-31"
+        );
+        assert_eq!(
+            parse_err("0 0 * 13 *"),
+            "month value 13 is out of range 1-12"
+        );
+        assert_eq!(
+            parse_err("0 0 * * 8"),
+            "day of week value 8 is out of range 0-7"
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(
+            parse_err("* * * *"),
+            "cron expression must have 5 fields, got 4"
+        );
+        assert_eq!(
+            parse_err("* * * * * *"),
+            "cron expression must have 5 fields, got 6"
+        );
+        assert_eq!(parse_err("x * * * *"), "invalid minute value 'x'");
+        assert_eq!(parse_err("1,,2 * * * *"), "invalid minute value ''");
+        assert_eq!(parse_err("*/0 * * * *"), "minute step must be positive");
+        assert_eq!(parse_err("*/x * * * *"), "invalid minute step 'x'");
+        assert_eq!(parse_err("0 5-3 * * *"), "hour range 5-3 is empty");
+        assert_eq!(
+            parse_err("0 0 * * MONDAY"),
+            "invalid day of week value 'MONDAY'"
+        );
+    }
+}
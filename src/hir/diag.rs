@@ -0,0 +1,233 @@
+//! Errors in workflow files, rendered with source snippets
+use std::{collections::HashMap, fmt, ops::Range, path::Path, sync::Arc};
+
+/// Workflow file
+#[derive(Debug)]
+pub struct Source {
+    pub path: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(path: impl Into<String>, text: String) -> Arc<Source> {
+        Arc::new(Source {
+            path: path.into(),
+            text,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Arc<Source>, Diagnostic> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Diagnostic::new(format!("failed read {}: {}", path.display(), err)))?;
+        Ok(Source::new(path.display().to_string(), text))
+    }
+}
+
+/// Location in a workflow file
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub source: Arc<Source>,
+    /// Byte range; `None` if only file is known
+    pub range: Option<Range<usize>>,
+}
+
+impl Span {
+    pub fn new(source: &Arc<Source>, range: Range<usize>) -> Span {
+        Span {
+            source: source.clone(),
+            range: Some(range),
+        }
+    }
+
+    pub fn file(source: &Arc<Source>) -> Span {
+        Span {
+            source: source.clone(),
+            range: None,
+        }
+    }
+}
+
+/// Spans of a definition and its keys
+#[derive(Debug, Clone)]
+pub struct DefSpans {
+    /// Header of the definition, e.g. `action "a"`
+    pub def: Span,
+    /// Key spans by key name; map entries are stored as `<key>.<entry>`
+    pub keys: HashMap<String, Span>,
+}
+
+impl DefSpans {
+    pub fn new(def: Span) -> DefSpans {
+        DefSpans {
+            def,
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Returns span of the key, or span of the definition if it is unknown
+    pub fn key(&self, key: &str) -> Span {
+        self.keys.get(key).unwrap_or(&self.def).clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    /// Related locations, which explain the error
+    pub notes: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn at(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            span: Some(span),
+            notes: vec![],
+        }
+    }
+
+    pub fn with_note(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.notes.push((span, message.into()));
+        self
+    }
+}
+
+/// Writes message with snippet of the source, if span is known
+fn write_message(
+    f: &mut fmt::Formatter,
+    level: &str,
+    message: &str,
+    span: Option<&Span>,
+) -> fmt::Result {
+    let span = match span {
+        Some(span) => span,
+        None => return write!(f, "{}: {}", level, message),
+    };
+    let range = match &span.range {
+        Some(range) => range,
+        None => return write!(f, "{}: {}: {}", level, span.source.path, message),
+    };
+    let pest_span = pest::Span::new(&span.source.text, range.start, range.end)
+        .expect("span is out of source bounds");
+    let err = pest::error::Error::<()>::new_from_span(
+        pest::error::ErrorVariant::CustomError {
+            message: message.to_string(),
+        },
+        pest_span,
+    )
+    .with_path(&span.source.path);
+    write!(f, "{}: {}", level, err)
+}
+
+impl Diagnostic {
+    /// Returns path of the file, where problem is located
+    pub fn path(&self) -> Option<&str> {
+        self.span.as_ref().map(|span| span.source.path.as_str())
+    }
+
+    fn render(&self, f: &mut fmt::Formatter, level: &str) -> fmt::Result {
+        write_message(f, level, &self.message, self.span.as_ref())?;
+        for (span, message) in &self.notes {
+            writeln!(f)?;
+            write_message(f, "note", message, Some(span))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.render(f, "error")
+    }
+}
+
+/// Errors found in workflow files
+#[derive(Debug, Default)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, diag: Diagnostic) {
+        self.0.push(diag);
+    }
+
+    pub fn append(&mut self, other: Diagnostics) {
+        self.0.extend(other.0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `value` if nothing was reported
+    pub fn finish<T>(self, value: T) -> Result<T, Diagnostics> {
+        if self.is_empty() {
+            Ok(value)
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Renders problems as warnings, for ones which do not stop the command
+    pub fn warnings(&self) -> Warnings<'_> {
+        Warnings(self)
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+fn render_all(diags: &Diagnostics, f: &mut fmt::Formatter, level: &str) -> fmt::Result {
+    for (i, diag) in diags.0.iter().enumerate() {
+        if i != 0 {
+            writeln!(f)?;
+        }
+        diag.render(f, level)?;
+    }
+    Ok(())
+}
+
+/// Displays diagnostics with `warning` level
+pub struct Warnings<'a>(&'a Diagnostics);
+
+impl fmt::Display for Warnings<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        render_all(self.0, f, "warning")
+    }
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diag: Diagnostic) -> Diagnostics {
+        Diagnostics(vec![diag])
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        render_all(self, f, "error")
+    }
+}
+
+impl std::error::Error for Diagnostics {}
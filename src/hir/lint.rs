@@ -1,38 +1,27 @@
-use super::Hir;
-use std::{cmp::max, process::exit};
-
-#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone)]
-enum LintOutcome {
-    Allow,
-    Error,
-}
-
-impl LintOutcome {
-    fn max_assign(&mut self, other: LintOutcome) {
-        *self = max(*self, other);
-    }
-}
+use super::{
+    diag::{Diagnostic, Diagnostics},
+    Hir,
+};
 
 /// Lints env vars with name, starting with "GITHUB_"
-fn lint_env_github(h: &Hir) -> LintOutcome {
-    let mut outcome = LintOutcome::Allow;
+fn lint_env_github(h: &Hir, diags: &mut Diagnostics) {
     for act in h.actions() {
         for var_name in act.env.keys() {
             if var_name.starts_with("GITHUB_") {
-                outcome.max_assign(LintOutcome::Error);
-                eprint!(
-                    "Error: in action '{}': in env var '{}': ",
-                    &act.name, var_name
-                );
-                eprintln!("var name starts with reserved prefix GITHUB_");
+                diags.push(Diagnostic::at(
+                    act.spans.key(&format!("env.{}", var_name)),
+                    format!(
+                        "in action '{}': in env var '{}': var name starts with reserved prefix GITHUB_",
+                        &act.name, var_name
+                    ),
+                ));
             }
         }
     }
-    outcome
 }
 
 /// Lints overflowing secrets limit
-fn lint_secrets_limit(h: &Hir) -> LintOutcome {
+fn lint_secrets_limit(h: &Hir, diags: &mut Diagnostics) {
     let mut secrets = std::collections::HashSet::new();
     for act in h.actions() {
         for secret in &act.secrets {
@@ -41,48 +30,36 @@ fn lint_secrets_limit(h: &Hir) -> LintOutcome {
     }
     const GITHUB_SECRET_COUNT_LIMIT: usize = 100;
     if secrets.len() > GITHUB_SECRET_COUNT_LIMIT {
-        eprintln!(
-            "Error: you are using {} secrets, which exceeds GitHub limit of {}",
+        diags.push(Diagnostic::new(format!(
+            "you are using {} secrets, which exceeds GitHub limit of {}",
             secrets.len(),
             GITHUB_SECRET_COUNT_LIMIT
-        );
-        LintOutcome::Error
-    } else {
-        LintOutcome::Allow
+        )));
     }
 }
 
 /// Lints empty `runs` or `args`
-fn lint_empty_arr(h: &Hir) -> LintOutcome {
-    let mut outcome = LintOutcome::Allow;
+fn lint_empty_arr(h: &Hir, diags: &mut Diagnostics) {
     for act in h.actions() {
-        let bad_runs = act.runs.as_ref().map(Vec::is_empty).unwrap_or(false);
-        let bad_args = act.args.as_ref().map(Vec::is_empty).unwrap_or(false);
-        if !bad_runs && !bad_args {
-            continue;
-        }
-        let print_loc = || {
-            eprint!("Error: in action '{}': ", &act.name);
-        };
-        if bad_runs {
-            print_loc();
-            eprintln!("runs is defined to empty value");
+        if act.runs.as_ref().is_some_and(Vec::is_empty) {
+            diags.push(Diagnostic::at(
+                act.spans.key("runs"),
+                format!("in action '{}': runs is defined to empty value", &act.name),
+            ));
         }
-        if bad_args {
-            print_loc();
-            eprintln!("args is defined to empty value");
+        if act.args.as_ref().is_some_and(Vec::is_empty) {
+            diags.push(Diagnostic::at(
+                act.spans.key("args"),
+                format!("in action '{}': args is defined to empty value", &act.name),
+            ));
         }
-        outcome.max_assign(LintOutcome::Error);
     }
-    outcome
 }
 
-pub fn lint(h: &Hir) {
-    let mut outcome = LintOutcome::Allow;
-    outcome.max_assign(lint_env_github(h));
-    outcome.max_assign(lint_secrets_limit(h));
-    outcome.max_assign(lint_empty_arr(h));
-    if outcome == LintOutcome::Error {
-        exit(1);
-    }
+pub fn lint(h: &Hir) -> Diagnostics {
+    let mut diags = Diagnostics::new();
+    lint_env_github(h, &mut diags);
+    lint_secrets_limit(h, &mut diags);
+    lint_empty_arr(h, &mut diags);
+    diags
 }
@@ -1,5 +1,6 @@
-use pest::{iterators::Pair, Parser, Span};
-use std::{collections::HashMap, process::exit};
+use super::diag::{DefSpans, Diagnostic, Diagnostics, Source, Span};
+use pest::{error::ErrorVariant, iterators::Pair, Parser};
+use std::{collections::HashMap, sync::Arc};
 
 #[derive(Parser)]
 #[grammar = "hir/workflow.pest"]
@@ -8,8 +9,11 @@ struct WfParser;
 #[derive(Debug, Clone)]
 pub struct WorkflowDef {
     pub name: String,
-    pub on: String,
+    pub on: Vec<String>,
     pub resolves: Vec<String>,
+    /// Workflow is lowered from YAML, which allows more triggers
+    pub yaml: bool,
+    pub spans: DefSpans,
 }
 
 #[derive(Debug, Clone)]
@@ -21,6 +25,7 @@ pub struct ActionDef {
     pub args: Option<Vec<String>>,
     pub env: HashMap<String, String>,
     pub secrets: Vec<String>,
+    pub spans: DefSpans,
 }
 
 #[derive(Debug, Clone)]
@@ -29,44 +34,126 @@ pub enum Def {
     Action(ActionDef),
 }
 
-fn trim_str(s: &str) -> &str {
-    let n = s.len();
-    &s[1..n - 1]
+/// Parsing state of a single file
+struct Ctx<'a> {
+    source: &'a Arc<Source>,
+    diags: Diagnostics,
 }
 
-fn fail_with_error(p: Span, err: impl std::string::ToString) -> ! {
-    let msg = err.to_string();
-    let err = pest::error::Error::<Rule>::new_from_span(
-        pest::error::ErrorVariant::CustomError { message: msg },
-        p,
-    );
-    eprintln!("error: {}", err);
-    exit(1);
+impl<'a> Ctx<'a> {
+    fn span(&self, p: pest::Span) -> Span {
+        Span::new(self.source, p.start()..p.end())
+    }
+
+    fn error(&mut self, p: pest::Span, msg: impl Into<String>) {
+        let span = self.span(p);
+        self.diags.push(Diagnostic::at(span, msg));
+    }
+
+    /// Removes key from `kvps` and converts its value, reporting type mismatch
+    fn take<T>(
+        &mut self,
+        kvps: &mut Kvps,
+        key: &str,
+        convert: fn(Value) -> Result<T, String>,
+    ) -> Option<T> {
+        let (val, span) = kvps.remove(key)?;
+        match convert(val) {
+            Ok(val) => Some(val),
+            Err(msg) => {
+                self.error(span, format!("{}: {}", key, msg));
+                None
+            }
+        }
+    }
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        // grammar only allows valid escapes
+        match chars.next().unwrap() {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            'u' => {
+                let code: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&code, 16).unwrap();
+                match std::char::from_u32(code) {
+                    Some(ch) => out.push(ch),
+                    None => return Err(format!("\\u{:04x} is not a valid character", code)),
+                }
+            }
+            ch => out.push(ch),
+        }
+    }
+    Ok(out)
+}
+
+fn heredoc_contents(s: &str) -> String {
+    let s = s.replace("\r\n", "\n");
+    let strip_indent = s.starts_with("<<-");
+    // skip header line and line with closing marker
+    let body = &s[s.find('\n').unwrap() + 1..];
+    let body = match body.rfind('\n') {
+        Some(pos) => &body[..=pos],
+        None => "",
+    };
+    if !strip_indent {
+        return body.to_string();
+    }
+    let indent = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+    body.lines()
+        .map(|line| format!("{}\n", line.get(indent..).unwrap_or("")))
+        .collect()
+}
+
+/// Decodes string literal
+fn parse_string(cx: &mut Ctx, p: &Pair<Rule>) -> String {
+    assert_eq!(p.as_rule(), Rule::string);
+    if p.as_str().starts_with("<<") {
+        return heredoc_contents(p.as_str());
+    }
+    unescape(p.as_str()).unwrap_or_else(|err| {
+        cx.error(p.as_span(), err);
+        String::new()
+    })
 }
 
-enum Value {
+/// Value of a key, as written in the file
+#[derive(Debug, Clone)]
+pub enum Value {
     String(String),
     Array(Vec<String>),
     Map(HashMap<String, String>),
 }
 
 impl Value {
-    fn array_or_from_string(self, p: Span) -> Vec<String> {
+    fn array_or_from_string(self) -> Result<Vec<String>, String> {
         match self {
-            Value::String(s) => vec![s],
-            Value::Array(a) => a,
-            Value::Map(_) => fail_with_error(p, "expected array or single string, got map"),
+            Value::String(s) => Ok(vec![s]),
+            Value::Array(a) => Ok(a),
+            Value::Map(_) => Err("expected array or single string, got map".to_string()),
         }
     }
 
-    fn array_or_split_string(self, p: Span) -> Vec<String> {
+    fn array_or_split_string(self) -> Result<Vec<String>, String> {
         match self {
-            Value::String(s) => s.split_ascii_whitespace().map(ToOwned::to_owned).collect(),
-            Value::Array(arr) => arr,
-            Value::Map(_) => fail_with_error(
-                p,
-                "expected array or string with space-separated items, got map",
-            ),
+            Value::String(s) => Ok(s.split_ascii_whitespace().map(ToOwned::to_owned).collect()),
+            Value::Array(arr) => Ok(arr),
+            Value::Map(_) => {
+                Err("expected array or string with space-separated items, got map".to_string())
+            }
         }
     }
 
@@ -78,57 +165,64 @@ impl Value {
         }
     }
 
-    fn string(self, p: Span) -> String {
+    fn string(self) -> Result<String, String> {
         match self {
-            Value::String(s) => s,
-            _ => fail_with_error(p, format!("expected string, got {}", self.ty())),
+            Value::String(s) => Ok(s),
+            _ => Err(format!("expected string, got {}", self.ty())),
         }
     }
 
-    fn array(self, p: Span) -> Vec<String> {
+    fn array(self) -> Result<Vec<String>, String> {
         match self {
-            Value::Array(a) => a,
-            _ => fail_with_error(p, format!("expected array, got {}", self.ty())),
+            Value::Array(a) => Ok(a),
+            _ => Err(format!("expected array, got {}", self.ty())),
         }
     }
 
-    fn map(self, p: Span) -> HashMap<String, String> {
+    fn map(self) -> Result<HashMap<String, String>, String> {
         match self {
-            Value::Map(m) => m,
-            _ => fail_with_error(p, format!("expected map, got {}", self.ty())),
+            Value::Map(m) => Ok(m),
+            _ => Err(format!("expected map, got {}", self.ty())),
         }
     }
 }
 
-struct KvPair {
+/// Children of a pair, except comments
+fn code(p: Pair<Rule>) -> impl Iterator<Item = Pair<Rule>> {
+    p.into_inner().filter(|x| x.as_rule() != Rule::COMMENT)
+}
+
+struct KvPair<'i> {
     ident: String,
     val: Value,
+    /// Spans of map entries
+    entry_spans: Vec<(String, pest::Span<'i>)>,
 }
 
-fn parse_kvp(p: Pair<Rule>) -> KvPair {
+fn parse_kvp<'i>(cx: &mut Ctx, p: Pair<'i, Rule>) -> KvPair<'i> {
     assert_eq!(p.as_rule(), Rule::kvp);
-    let mut iter = p.into_inner();
+    let mut iter = code(p);
     let ident = iter.next().unwrap();
     let value = iter.next().unwrap();
+    let mut entry_spans = vec![];
     let val = match value.as_rule() {
-        Rule::string => Value::String(trim_str(value.as_str()).to_string()),
+        Rule::string => Value::String(parse_string(cx, &value)),
         Rule::array => {
             let mut out = vec![];
-            for x in value.into_inner() {
-                out.push(trim_str(x.as_str()).to_string());
+            for x in code(value) {
+                out.push(parse_string(cx, &x));
             }
             Value::Array(out)
         }
         Rule::map => {
             let mut out = HashMap::new();
-            let mut iter = value.into_inner();
+            let mut iter = code(value);
             while let Some(ident) = iter.next() {
                 assert_eq!(ident.as_rule(), Rule::ident);
                 let val = iter.next().unwrap();
-                out.insert(
-                    ident.as_str().to_string(),
-                    trim_str(val.as_str()).to_string(),
-                );
+                let span = ident.as_span().start_pos().span(&val.as_span().end_pos());
+                entry_spans.push((ident.as_str().to_string(), span));
+                out.insert(ident.as_str().to_string(), parse_string(cx, &val));
             }
             Value::Map(out)
         }
@@ -140,108 +234,594 @@ fn parse_kvp(p: Pair<Rule>) -> KvPair {
     KvPair {
         ident: ident.as_str().to_string(),
         val,
+        entry_spans,
     }
 }
 
-fn parse_def_body(p: Pair<Rule>, required: &[&str]) -> (String, HashMap<String, Value>) {
+/// Values of keys with their spans
+type Kvps<'i> = HashMap<String, (Value, pest::Span<'i>)>;
+
+fn parse_def_body<'i>(
+    cx: &mut Ctx,
+    p: Pair<'i, Rule>,
+    required: &[&str],
+) -> (String, Kvps<'i>, DefSpans) {
     let mut out = HashMap::new();
-    let all_span = p.as_span();
-    let mut iter = p.into_inner();
-    let name = iter.next().unwrap().as_str();
+    let def_start = p.as_span().start_pos();
+    let mut iter = code(p);
+    let name_pair = iter.next().unwrap();
+    let name = parse_string(cx, &name_pair);
+    let header = def_start.span(&name_pair.as_span().end_pos());
+    let mut spans = DefSpans::new(cx.span(header.clone()));
     for item in iter {
         let item_span = item.as_span();
-        let KvPair { ident, val } = parse_kvp(item);
-        if out.insert(ident.clone(), val).is_some() {
-            fail_with_error(item_span, format!("key {} redefined", ident));
+        let KvPair {
+            ident,
+            val,
+            entry_spans,
+        } = parse_kvp(cx, item);
+        if out.contains_key(&ident) {
+            cx.error(item_span, format!("key {} redefined", ident));
+            continue;
+        }
+        for (entry, span) in entry_spans {
+            let span = cx.span(span);
+            spans.keys.insert(format!("{}.{}", ident, entry), span);
         }
+        spans.keys.insert(ident.clone(), cx.span(item_span.clone()));
+        out.insert(ident, (val, item_span));
     }
     for &req in required {
         if !out.contains_key(req) {
-            fail_with_error(all_span, format!("key {} required but not set", req));
+            cx.error(header.clone(), format!("key {} required but not set", req));
         }
     }
-    (trim_str(name).to_string(), out)
+    (name, out, spans)
 }
 
-fn check_kvps_empty(p: Span, k: &HashMap<String, Value>) {
-    if let Some(some_key) = k.keys().next() {
-        fail_with_error(p, format!("unexpected key {}", some_key))
+fn check_kvps_empty(cx: &mut Ctx, k: &Kvps) {
+    let mut unexpected: Vec<_> = k.iter().collect();
+    unexpected.sort_by_key(|(_, (_, span))| span.start());
+    for (key, (_, span)) in unexpected {
+        cx.error(span.clone(), format!("unexpected key {}", key));
     }
 }
 
-fn parse_workflow_def(p: Pair<Rule>) -> WorkflowDef {
+fn parse_workflow_def(cx: &mut Ctx, p: Pair<Rule>) -> WorkflowDef {
     assert_eq!(p.as_rule(), Rule::workflow_def);
-    let span = p.as_span();
-    let (name, mut kvps) = parse_def_body(p, &["on", "resolves"]);
+    let (name, mut kvps, spans) = parse_def_body(cx, p, &["on", "resolves"]);
     let out = WorkflowDef {
         name,
 
-        on: kvps.remove("on").unwrap().string(span.clone()),
-        resolves: kvps
-            .remove("resolves")
-            .unwrap()
-            .array_or_from_string(span.clone()),
+        on: cx
+            .take(&mut kvps, "on", Value::string)
+            .into_iter()
+            .collect(),
+        resolves: cx
+            .take(&mut kvps, "resolves", Value::array_or_from_string)
+            .unwrap_or_default(),
+        yaml: false,
+        spans,
     };
-    check_kvps_empty(span, &kvps);
+    check_kvps_empty(cx, &kvps);
     out
 }
 
-fn parse_action_def(p: Pair<Rule>) -> ActionDef {
+fn parse_action_def(cx: &mut Ctx, p: Pair<Rule>) -> ActionDef {
     assert_eq!(p.as_rule(), Rule::action_def);
-    let span = p.as_span();
-    let (name, mut kvps) = parse_def_body(p, &["uses"]);
+    let (name, mut kvps, spans) = parse_def_body(cx, p, &["uses"]);
     let out = ActionDef {
         name,
 
-        uses: kvps.remove("uses").unwrap().string(span.clone()),
-        needs: kvps
-            .remove("needs")
-            .map(|x| x.array_or_from_string(span.clone()))
+        uses: cx
+            .take(&mut kvps, "uses", Value::string)
             .unwrap_or_default(),
-        runs: kvps
-            .remove("runs")
-            .map(|x| x.array_or_split_string(span.clone())),
-        args: kvps
-            .remove("args")
-            .map(|x| x.array_or_split_string(span.clone())),
-        env: kvps
-            .remove("env")
-            .map(|x| x.map(span.clone()))
+        needs: cx
+            .take(&mut kvps, "needs", Value::array_or_from_string)
             .unwrap_or_default(),
-        secrets: kvps
-            .remove("secrets")
-            .map(|x| x.array(span.clone()))
+        runs: cx.take(&mut kvps, "runs", Value::array_or_split_string),
+        args: cx.take(&mut kvps, "args", Value::array_or_split_string),
+        env: cx.take(&mut kvps, "env", Value::map).unwrap_or_default(),
+        secrets: cx
+            .take(&mut kvps, "secrets", Value::array)
             .unwrap_or_default(),
+        spans,
     };
 
-    check_kvps_empty(span, &kvps);
+    check_kvps_empty(cx, &kvps);
+    out
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockKind {
+    Workflow,
+    Action,
+}
+
+impl BlockKind {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            BlockKind::Workflow => "workflow",
+            BlockKind::Action => "action",
+        }
+    }
+}
+
+/// Comments, attached to a definition, key or map entry
+#[derive(Debug, Clone, Default)]
+pub struct Comments {
+    /// Comments on lines before the item
+    pub before: Vec<String>,
+    /// Comment on the same line after the item
+    pub trailing: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    /// Comments inside of the value are moved before the entry, except ones in maps
+    pub comments: Comments,
+    /// Comments of map entries, by map key
+    pub map_comments: HashMap<String, Comments>,
+}
+
+/// Definition as written in the file, before any validation.
+/// Used by tools, which need to preserve file contents (e.g. formatter).
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub name: String,
+    /// Comments before the definition and on the line of its header
+    pub comments: Comments,
+    pub entries: Vec<Entry>,
+    /// Comments after the last entry
+    pub end_comments: Vec<String>,
+}
+
+/// Contents of main.workflow file
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+    /// Comments after the last definition
+    pub end_comments: Vec<String>,
+}
+
+fn start_line(p: &Pair<Rule>) -> usize {
+    p.as_span().start_pos().line_col().0
+}
+
+fn end_line(p: &Pair<Rule>) -> usize {
+    p.as_span().end_pos().line_col().0
+}
+
+/// Attaches comment to the item, ending on the same line, or to the following one
+fn attach_comment(
+    comment: Pair<Rule>,
+    prev: Option<(usize, &mut Comments)>,
+    pending: &mut Vec<String>,
+) {
+    let text = comment.as_str().to_string();
+    if let Some((line, prev)) = prev {
+        if line == start_line(&comment) && prev.trailing.is_none() {
+            prev.trailing = Some(text);
+            return;
+        }
+    }
+    pending.push(text);
+}
+
+fn parse_map_comments(
+    p: Pair<Rule>,
+    entry_comments: &mut Vec<String>,
+) -> HashMap<String, Comments> {
+    let mut out: HashMap<String, Comments> = HashMap::new();
+    let mut pending = vec![];
+    let mut key = None;
+    let mut last = None;
+    for x in p.into_inner() {
+        match x.as_rule() {
+            Rule::COMMENT => {
+                let prev = last
+                    .as_ref()
+                    .map(|(line, key)| (*line, out.get_mut(key).unwrap()));
+                attach_comment(x, prev, &mut pending);
+            }
+            Rule::ident => key = Some(x.as_str().to_string()),
+            Rule::string => {
+                let key = key.take().unwrap();
+                out.entry(key.clone())
+                    .or_default()
+                    .before
+                    .append(&mut pending);
+                last = Some((end_line(&x), key));
+            }
+            _ => unreachable!(),
+        }
+    }
+    entry_comments.append(&mut pending);
     out
 }
 
-fn parse_def(p: Pair<Rule>) -> Def {
+fn parse_entry(cx: &mut Ctx, p: Pair<Rule>, before: Vec<String>) -> Entry {
+    let mut comments = Comments {
+        before,
+        trailing: None,
+    };
+    let mut map_comments = HashMap::new();
+    for x in p.clone().into_inner() {
+        match x.as_rule() {
+            Rule::COMMENT => comments.before.push(x.as_str().to_string()),
+            Rule::map => map_comments = parse_map_comments(x, &mut comments.before),
+            Rule::array => comments.before.extend(
+                x.into_inner()
+                    .filter(|x| x.as_rule() == Rule::COMMENT)
+                    .map(|x| x.as_str().to_string()),
+            ),
+            _ => (),
+        }
+    }
+    let KvPair { ident, val, .. } = parse_kvp(cx, p);
+    Entry {
+        key: ident,
+        value: val,
+        comments,
+        map_comments,
+    }
+}
+
+fn parse_block(cx: &mut Ctx, p: Pair<Rule>, before: Vec<String>) -> Block {
+    let kind = match p.as_rule() {
+        Rule::workflow_def => BlockKind::Workflow,
+        Rule::action_def => BlockKind::Action,
+        _ => unreachable!(),
+    };
+    let mut comments = Comments {
+        before,
+        trailing: None,
+    };
+    let mut name = String::new();
+    let mut entries: Vec<Entry> = vec![];
+    let mut pending = vec![];
+    let mut last_line = None;
+    for x in p.into_inner() {
+        match x.as_rule() {
+            Rule::COMMENT => {
+                let prev = match entries.last_mut() {
+                    Some(entry) => &mut entry.comments,
+                    None => &mut comments,
+                };
+                attach_comment(x, last_line.map(|line| (line, prev)), &mut pending);
+            }
+            Rule::string => {
+                name = parse_string(cx, &x);
+                comments.before.append(&mut pending);
+                last_line = Some(end_line(&x));
+            }
+            Rule::kvp => {
+                last_line = Some(end_line(&x));
+                entries.push(parse_entry(cx, x, std::mem::take(&mut pending)));
+            }
+            _ => unreachable!(),
+        }
+    }
+    Block {
+        kind,
+        name,
+        comments,
+        entries,
+        end_comments: pending,
+    }
+}
+
+fn parse_def(cx: &mut Ctx, p: Pair<Rule>) -> Def {
     match p.as_rule() {
-        Rule::workflow_def => Def::Workflow(parse_workflow_def(p)),
-        Rule::action_def => Def::Action(parse_action_def(p)),
+        Rule::workflow_def => Def::Workflow(parse_workflow_def(cx, p)),
+        Rule::action_def => Def::Action(parse_action_def(cx, p)),
         _ => unreachable!(),
     }
 }
 
-pub fn parse(s: &str) -> Vec<Def> {
-    let mut tree = match WfParser::parse(Rule::all, s) {
-        Ok(x) => x,
-        Err(err) => {
-            eprintln!("syntax error: {}", err);
-            std::process::exit(1);
+fn rule_name(rule: &Rule) -> String {
+    match rule {
+        Rule::EOI => "end of file".to_string(),
+        Rule::workflow_def => "workflow".to_string(),
+        Rule::action_def => "action".to_string(),
+        Rule::kvp => "key".to_string(),
+        Rule::ident => "identifier".to_string(),
+        rule => format!("{:?}", rule),
+    }
+}
+
+fn join_rules(rules: &[Rule]) -> String {
+    let names: Vec<_> = rules
+        .iter()
+        .filter(|&&rule| rule != Rule::COMMENT)
+        .map(rule_name)
+        .collect();
+    match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => names.join(""),
+    }
+}
+
+fn syntax_error(source: &Arc<Source>, err: pest::error::Error<Rule>) -> Diagnostic {
+    let range = match err.location {
+        pest::error::InputLocation::Pos(pos) => pos..pos,
+        pest::error::InputLocation::Span((start, end)) => start..end,
+    };
+    let message = match &err.variant {
+        ErrorVariant::ParsingError {
+            positives,
+            negatives,
+        } => match (positives.is_empty(), negatives.is_empty()) {
+            (false, true) => format!("expected {}", join_rules(positives)),
+            (true, false) => format!("unexpected {}", join_rules(negatives)),
+            (false, false) => format!(
+                "unexpected {}; expected {}",
+                join_rules(negatives),
+                join_rules(positives)
+            ),
+            (true, true) => "unknown parsing error".to_string(),
+        },
+        ErrorVariant::CustomError { message } => message.clone(),
+    };
+    Diagnostic::at(
+        Span::new(source, range),
+        format!("syntax error: {}", message),
+    )
+}
+
+fn parse_syntax(source: &Arc<Source>) -> Result<Vec<Pair<'_, Rule>>, Diagnostics> {
+    let mut tree =
+        WfParser::parse(Rule::all, &source.text).map_err(|err| syntax_error(source, err))?;
+    Ok(tree
+        .next()
+        .unwrap()
+        .into_inner()
+        .filter(|x| x.as_rule() != Rule::EOI)
+        .collect())
+}
+
+/// Parses file, adding errors to `diags`.
+///
+/// Definitions with invalid keys are still returned, so that later stages can report
+/// their errors too. Returns `None` on syntax error.
+pub fn parse_partial(source: &Arc<Source>, diags: &mut Diagnostics) -> Option<Vec<Def>> {
+    let pairs = match parse_syntax(source) {
+        Ok(pairs) => pairs,
+        Err(errors) => {
+            diags.append(errors);
+            return None;
         }
     };
-    let mut res = Vec::new();
-    let defs = tree.next().unwrap();
-    for x in defs.into_inner() {
-        if x.as_rule() == Rule::EOI {
-            break;
+    let mut cx = Ctx {
+        source,
+        diags: Diagnostics::new(),
+    };
+    let defs = pairs
+        .into_iter()
+        .filter(|x| x.as_rule() != Rule::COMMENT)
+        .map(|x| parse_def(&mut cx, x))
+        .collect();
+    diags.append(cx.diags);
+    Some(defs)
+}
+
+pub fn parse(source: &Arc<Source>) -> Result<Vec<Def>, Diagnostics> {
+    let mut diags = Diagnostics::new();
+    match parse_partial(source, &mut diags) {
+        Some(defs) => diags.finish(defs),
+        None => Err(diags),
+    }
+}
+
+/// Parses file without checking keys and their types, preserving comments
+pub fn parse_blocks(source: &Arc<Source>) -> Result<Document, Diagnostics> {
+    let mut cx = Ctx {
+        source,
+        diags: Diagnostics::new(),
+    };
+    let mut blocks = vec![];
+    let mut pending = vec![];
+    for x in parse_syntax(source)? {
+        if x.as_rule() == Rule::COMMENT {
+            pending.push(x.as_str().to_string());
+        } else {
+            blocks.push(parse_block(&mut cx, x, std::mem::take(&mut pending)));
+        }
+    }
+    cx.diags.finish(Document {
+        blocks,
+        end_comments: pending,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `value` as value of env variable
+    fn env_value(value: &str) -> String {
+        let src = format!(
+            "action \"a\" {{\n  uses = \"docker://alpine\"\n  env = {{\n    X = {}\n  }}\n}}\n",
+            value
+        );
+        match parse(&Source::new("test", src)).unwrap().pop().unwrap() {
+            Def::Action(mut act) => act.env.remove("X").unwrap(),
+            Def::Workflow(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn plain_string() {
+        assert_eq!(env_value(r#""hello world""#), "hello world");
+        assert_eq!(env_value(r#""""#), "");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(env_value(r#""say \"hi\"""#), "say \"hi\"");
+        assert_eq!(env_value(r#""back\\slash""#), "back\\slash");
+        assert_eq!(env_value(r#""a\nb""#), "a\nb");
+        assert_eq!(env_value(r#""a\tb""#), "a\tb");
+        assert_eq!(env_value(r#""a\rb""#), "a\rb");
+        assert_eq!(env_value(r#""\u00e9\u2603""#), "é☃");
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        for s in &[r#""\q""#, r#""\u12""#, r#""trailing\""#] {
+            assert!(WfParser::parse(Rule::string, s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn heredoc() {
+        assert_eq!(
+            env_value("<<EOF\nline 1\n  line \"2\" \\n\nEOF"),
+            "line 1\n  line \"2\" \\n\n"
+        );
+        assert_eq!(env_value("<<EOF\nEOF"), "");
+        // marker must be the whole line
+        assert_eq!(env_value("<<END\nEND2\nno END\nEND"), "END2\nno END\n");
+        assert_eq!(
+            env_value("<<EOF\nEOF done\nEOF-x\nEOF.\nEOF  "),
+            "EOF done\nEOF-x\nEOF.\n"
+        );
+        assert_eq!(env_value("<<EOF\r\nwindows\r\nEOF"), "windows\n");
+    }
+
+    #[test]
+    fn indented_heredoc() {
+        assert_eq!(
+            env_value("<<-EOF\n    if true; then\n      echo ok\n\n    fi\n    EOF"),
+            "if true; then\n  echo ok\n\nfi\n"
+        );
+    }
+
+    #[test]
+    fn unterminated_heredoc() {
+        for value in &["<<EOF\nEOF done", "<<EOF\nEOF.", "<<-EOF\n  text\n  EOF-x"] {
+            let src = format!(
+                "action \"a\" {{\n  uses = \"docker://alpine\"\n  env = {{\n    X = {}\n  }}\n}}\n",
+                value
+            );
+            assert!(parse(&Source::new("test", src)).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn heredoc_in_array() {
+        let src = "action \"a\" {\n  uses = \"docker://alpine\"\n  runs = [\"sh\", \"-c\", <<EOF\necho hi\nEOF\n  ]\n}\n";
+        match parse(&Source::new("test", src.to_string()))
+            .unwrap()
+            .pop()
+            .unwrap()
+        {
+            Def::Action(act) => assert_eq!(
+                act.runs.unwrap(),
+                vec!["sh".to_string(), "-c".to_string(), "echo hi\n".to_string()]
+            ),
+            Def::Workflow(_) => unreachable!(),
+        }
+    }
+
+    /// Comments in all positions, where they are preserved
+    const COMMENTED: &str = r#"# before workflow
+// also before workflow
+workflow "ci" { # header
+  /* before on */
+  on = "push" // on
+  resolves = [
+    # inside array
+    "a", /* after item */
+    "b"
+  ] # resolves
+  # end of workflow
+}
+/* between */
+action "a" {
+  uses = "docker://alpine"
+  env = { // env
+    # before A
+    A = "1" # a
+    B = "2"
+    // end of map
+  }
+}
+# end of file
+/* multi
+   line */
+"#;
+
+    fn comments(before: &[&str], trailing: Option<&str>) -> (Vec<String>, Option<String>) {
+        (
+            before.iter().map(ToString::to_string).collect(),
+            trailing.map(ToString::to_string),
+        )
+    }
+
+    fn attached(c: &Comments) -> (Vec<String>, Option<String>) {
+        (c.before.clone(), c.trailing.clone())
+    }
+
+    #[test]
+    fn comments_are_attached() {
+        let doc = parse_blocks(&Source::new("test", COMMENTED.to_string())).unwrap();
+        assert_eq!(doc.blocks.len(), 2);
+
+        let wf = &doc.blocks[0];
+        assert_eq!(
+            attached(&wf.comments),
+            comments(
+                &["# before workflow", "// also before workflow"],
+                Some("# header")
+            )
+        );
+        assert_eq!(
+            attached(&wf.entries[0].comments),
+            comments(&["/* before on */"], Some("// on"))
+        );
+        // comments inside of arrays are moved before the entry
+        assert_eq!(
+            attached(&wf.entries[1].comments),
+            comments(&["# inside array", "/* after item */"], Some("# resolves"))
+        );
+        assert_eq!(wf.end_comments, ["# end of workflow"]);
+
+        let act = &doc.blocks[1];
+        assert_eq!(attached(&act.comments), comments(&["/* between */"], None));
+        assert_eq!(attached(&act.entries[0].comments), comments(&[], None));
+        let env = &act.entries[1];
+        // comments after the last map entry are moved before the entry
+        assert_eq!(attached(&env.comments), comments(&["// end of map"], None));
+        // comment after `{` precedes the first map entry
+        assert_eq!(
+            attached(&env.map_comments["A"]),
+            comments(&["// env", "# before A"], Some("# a"))
+        );
+        assert_eq!(attached(&env.map_comments["B"]), comments(&[], None));
+        assert!(act.end_comments.is_empty());
+
+        assert_eq!(doc.end_comments, ["# end of file", "/* multi\n   line */"]);
+    }
+
+    #[test]
+    fn formatting_keeps_comments() {
+        let source = Source::new("test", COMMENTED.to_string());
+        let formatted = crate::fmt::format(&parse_blocks(&source).unwrap());
+        let mut comments = vec![];
+        for p in parse_syntax(&source).unwrap() {
+            comments.push(p.clone());
+            comments.extend(p.into_inner().flatten());
+        }
+        comments.retain(|x| x.as_rule() == Rule::COMMENT);
+        assert_eq!(comments.len(), 16);
+        for comment in comments {
+            assert!(formatted.contains(comment.as_str()), "{}", formatted);
         }
-        let def = parse_def(x);
-        res.push(def);
+        let doc = parse_blocks(&Source::new("test", formatted.clone())).unwrap();
+        assert_eq!(crate::fmt::format(&doc), formatted);
     }
-    res
 }
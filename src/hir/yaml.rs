@@ -0,0 +1,811 @@
+//! Front-end for YAML workflows from `.github/workflows/*.yml`.
+//!
+//! Every step is lowered into an action, named `<workflow>/<job>/<step>`.
+//! Steps of a job run one after another, and the first step of a job needs
+//! last steps of all jobs it needs.
+use super::{
+    diag::{DefSpans, Diagnostic, Diagnostics, Source, Span},
+    parser::{ActionDef, Def, WorkflowDef},
+};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Arc,
+};
+
+#[derive(Deserialize)]
+struct WorkflowFile {
+    name: Option<String>,
+    on: Value,
+    #[serde(default)]
+    env: BTreeMap<String, Value>,
+    jobs: BTreeMap<String, Job>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum OneOrMany {
+    #[default]
+    None,
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn to_vec(&self) -> Vec<String> {
+        match self {
+            OneOrMany::None => vec![],
+            OneOrMany::One(s) => vec![s.clone()],
+            OneOrMany::Many(v) => v.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Container {
+    Image(String),
+    Spec { image: String },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Job {
+    #[serde(default)]
+    runs_on: OneOrMany,
+    #[serde(default)]
+    needs: OneOrMany,
+    container: Option<Container>,
+    #[serde(default)]
+    env: BTreeMap<String, Value>,
+    #[serde(rename = "if")]
+    condition: Option<Value>,
+    strategy: Option<Value>,
+    /// Reusable workflow reference
+    uses: Option<String>,
+    #[serde(default)]
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Step {
+    id: Option<String>,
+    name: Option<String>,
+    uses: Option<String>,
+    run: Option<String>,
+    shell: Option<String>,
+    working_directory: Option<String>,
+    #[serde(default)]
+    with: BTreeMap<String, Value>,
+    #[serde(default)]
+    env: BTreeMap<String, Value>,
+    #[serde(rename = "if")]
+    condition: Option<Value>,
+}
+
+fn scalar_to_string(v: &Value) -> Option<String> {
+    match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Splits `with.args` into words like POSIX shell does, honoring quotes and backslashes.
+/// Variables and other expansions are not performed.
+fn split_args(s: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            _ if ch.is_whitespace() => words.extend(word.take()),
+            '\\' => {
+                let escaped = chars.next().ok_or("args end with backslash")?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or("args have unterminated single quote")? {
+                        '\'' => break,
+                        ch => word.push(ch),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or("args have unterminated double quote")? {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(ch @ '"') | Some(ch @ '\\') => word.push(ch),
+                            Some(ch) => {
+                                word.push('\\');
+                                word.push(ch);
+                            }
+                            None => return Err("args have unterminated double quote".to_string()),
+                        },
+                        ch => word.push(ch),
+                    }
+                }
+            }
+            _ => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Returns triggers in format of `on` key of main.workflow
+fn lower_on(
+    source: &Arc<Source>,
+    on: &Value,
+    warnings: &mut Diagnostics,
+) -> Result<Vec<String>, String> {
+    match on {
+        Value::String(s) => Ok(vec![s.clone()]),
+        Value::Sequence(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err("`on` list must contain event names".to_string()),
+            })
+            .collect(),
+        Value::Mapping(events) => {
+            let mut out = vec![];
+            for (name, filter) in events {
+                let name = match name {
+                    Value::String(s) => s.as_str(),
+                    _ => return Err("`on` keys must be event names".to_string()),
+                };
+                if name == "schedule" {
+                    let crons = match filter {
+                        Value::Sequence(items) => items,
+                        _ => return Err("`schedule` must be a list of `cron` items".to_string()),
+                    };
+                    for item in crons {
+                        match item.get("cron").and_then(Value::as_str) {
+                            Some(cron) => out.push(format!("schedule({})", cron)),
+                            None => {
+                                return Err("`schedule` item must have `cron` string".to_string())
+                            }
+                        }
+                    }
+                    continue;
+                }
+                if !filter.is_null() {
+                    warnings.push(Diagnostic::at(
+                        Span::file(source),
+                        format!("filters of event {} are ignored", name),
+                    ));
+                }
+                out.push(name.to_string());
+            }
+            Ok(out)
+        }
+        _ => Err("`on` must be event name, list or map".to_string()),
+    }
+}
+
+/// Returns image used for `run` steps of job
+fn job_image(job: &Job) -> Option<String> {
+    if let Some(container) = &job.container {
+        return Some(match container {
+            Container::Image(image) | Container::Spec { image } => image.clone(),
+        });
+    }
+    let label = match &job.runs_on {
+        OneOrMany::One(label) => label.as_str(),
+        _ => return None,
+    };
+    let version = label.strip_prefix("ubuntu-")?;
+    Some(format!("ubuntu:{}", version))
+}
+
+struct Lowering<'a> {
+    source: &'a Arc<Source>,
+    workflow_name: &'a str,
+    workflow_env: &'a BTreeMap<String, Value>,
+    jobs: &'a BTreeMap<String, Job>,
+    defs: Vec<Def>,
+    /// job id -> actions, which dependents of this job must wait for
+    tails: HashMap<&'a str, Vec<String>>,
+    /// jobs, which are being lowered now
+    in_progress: Vec<&'a str>,
+    diags: Diagnostics,
+    /// Things, which are lowered not exactly
+    warnings: Diagnostics,
+}
+
+impl<'a> Lowering<'a> {
+    fn error(&mut self, msg: String) {
+        self.diags
+            .push(Diagnostic::at(Span::file(self.source), msg));
+    }
+
+    fn warn(&mut self, msg: String) {
+        self.warnings
+            .push(Diagnostic::at(Span::file(self.source), msg));
+    }
+
+    /// Adds env var to `env`, or to `secrets` if it refers to secret with the same name
+    fn add_env(
+        &mut self,
+        ctx: &str,
+        key: &str,
+        val: &Value,
+        env: &mut HashMap<String, String>,
+        secrets: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let val = scalar_to_string(val)
+            .ok_or_else(|| format!("{}: env var {} must be scalar", ctx, key))?;
+        let expr = val.trim();
+        if expr.starts_with("${{") && expr.ends_with("}}") {
+            let inner = expr[3..expr.len() - 2].trim();
+            if let Some(secret) = inner.strip_prefix("secrets.") {
+                if secret != key {
+                    return Err(format!(
+                        "{}: env var {} refers to secret {}; only `{}: ${{{{ secrets.{} }}}}` form is supported",
+                        ctx, key, secret, key, key
+                    ));
+                }
+                env.remove(key);
+                if !secrets.iter().any(|s| s == key) {
+                    secrets.push(key.to_string());
+                }
+                return Ok(());
+            }
+        }
+        if val.contains("${{") {
+            self.warn(format!(
+                "{}: expressions are not evaluated in env var {}",
+                ctx, key
+            ));
+        }
+        secrets.retain(|s| s != key);
+        env.insert(key.to_string(), val);
+        Ok(())
+    }
+
+    fn lower_step(
+        &mut self,
+        job_id: &str,
+        job: &Job,
+        step: &Step,
+        name: String,
+    ) -> Result<Option<ActionDef>, String> {
+        let ctx = name.as_str();
+        if step.condition.is_some() {
+            self.warn(format!(
+                "{}: `if` is not supported, step will always run",
+                ctx
+            ));
+        }
+        let mut env = HashMap::new();
+        let mut secrets = vec![];
+        // narrower scopes override wider ones
+        for scope in [self.workflow_env, &job.env, &step.env].iter() {
+            for (key, val) in scope.iter() {
+                self.add_env(ctx, key, val, &mut env, &mut secrets)?;
+            }
+        }
+        let (uses, runs, args) = match (&step.uses, &step.run) {
+            (Some(_), Some(_)) => return Err(format!("{}: both `uses` and `run` are set", ctx)),
+            (None, None) => return Err(format!("{}: neither `uses` nor `run` is set", ctx)),
+            (Some(uses), None) => {
+                if uses.starts_with("actions/checkout@") {
+                    // workspace already contains copy of the repository
+                    return Ok(None);
+                }
+                let mut runs = None;
+                let mut args = None;
+                for (key, val) in &step.with {
+                    let val = scalar_to_string(val)
+                        .ok_or_else(|| format!("{}: input {} must be scalar", ctx, key))?;
+                    match key.as_str() {
+                        "entrypoint" => runs = Some(vec![val]),
+                        "args" => {
+                            args =
+                                Some(split_args(&val).map_err(|err| format!("{}: {}", ctx, err))?)
+                        }
+                        _ => {
+                            let var =
+                                format!("INPUT_{}", key.replace(' ', "_").to_ascii_uppercase());
+                            env.insert(var, val);
+                        }
+                    }
+                }
+                (uses.clone(), runs, args)
+            }
+            (None, Some(script)) => {
+                let image = job_image(job).ok_or_else(|| {
+                    format!(
+                        "job {}: runs-on has no docker image equivalent, please set `container`",
+                        job_id
+                    )
+                })?;
+                let default_shell = if job.container.is_some() {
+                    "sh"
+                } else {
+                    "bash"
+                };
+                let mut runs: Vec<String> = match step.shell.as_deref().unwrap_or(default_shell) {
+                    "bash" => ["bash", "--noprofile", "--norc", "-eo", "pipefail", "-c"]
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect(),
+                    "sh" => vec!["sh".to_string(), "-e".to_string(), "-c".to_string()],
+                    other => return Err(format!("{}: shell {} is not supported", ctx, other)),
+                };
+                let script = match &step.working_directory {
+                    Some(dir) => format!("cd '{}'\n{}", dir.replace('\'', "'\\''"), script),
+                    None => script.clone(),
+                };
+                runs.push(script);
+                (format!("docker://{}", image), Some(runs), None)
+            }
+        };
+        secrets.sort();
+        Ok(Some(ActionDef {
+            name,
+            uses,
+            needs: vec![],
+            runs,
+            args,
+            env,
+            secrets,
+            spans: DefSpans::new(Span::file(self.source)),
+        }))
+    }
+
+    /// Lowers job and returns actions, which dependents must wait for
+    fn lower_job(&mut self, job_id: &'a str) -> Vec<String> {
+        if let Some(tails) = self.tails.get(job_id) {
+            return tails.clone();
+        }
+        if self.in_progress.contains(&job_id) {
+            let msg = format!(
+                "jobs are cycled: {} -> {}",
+                self.in_progress.join(" -> "),
+                job_id
+            );
+            self.error(msg);
+            return vec![];
+        }
+        let job = &self.jobs[job_id];
+        if job.uses.is_some() {
+            self.error(format!(
+                "job {}: reusable workflows are not supported",
+                job_id
+            ));
+            self.tails.insert(job_id, vec![]);
+            return vec![];
+        }
+        if job.condition.is_some() {
+            self.warn(format!(
+                "job {}: `if` is not supported, job will always run",
+                job_id
+            ));
+        }
+        if job.strategy.is_some() {
+            self.warn(format!(
+                "job {}: `strategy` is not supported and ignored",
+                job_id
+            ));
+        }
+
+        self.in_progress.push(job_id);
+        let mut prev = vec![];
+        for dep in &job.needs.to_vec() {
+            match self.jobs.get_key_value(dep.as_str()) {
+                Some((dep, _)) => prev.extend(self.lower_job(dep)),
+                None => self.error(format!("job {} needs unknown job {}", job_id, dep)),
+            }
+        }
+        self.in_progress.pop();
+
+        let mut used_labels: Vec<String> = vec![];
+        for (i, step) in job.steps.iter().enumerate() {
+            let mut label = step
+                .id
+                .clone()
+                .or_else(|| step.name.clone())
+                .unwrap_or_else(|| format!("step-{}", i + 1));
+            if used_labels.contains(&label) {
+                label = format!("{}-{}", label, i + 1);
+            }
+            used_labels.push(label.clone());
+            let name = format!("{}/{}/{}", self.workflow_name, job_id, label);
+            match self.lower_step(job_id, job, step, name) {
+                Ok(Some(mut def)) => {
+                    def.needs = std::mem::replace(&mut prev, vec![def.name.clone()]);
+                    self.defs.push(Def::Action(def));
+                }
+                Ok(None) => (),
+                Err(msg) => self.error(msg),
+            }
+        }
+        self.tails.insert(job_id, prev.clone());
+        prev
+    }
+}
+
+/// Default workflow name, used when `name` is not set
+fn file_stem(source: &Source) -> String {
+    Path::new(&source.path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Returns name of the workflow, defined in the file, even if the file can't be lowered
+pub fn workflow_name(source: &Source) -> String {
+    serde_yaml::from_str::<Value>(&source.text)
+        .ok()
+        .and_then(|file| file.get("name").and_then(Value::as_str).map(str::to_string))
+        .unwrap_or_else(|| file_stem(source))
+}
+
+/// Parses YAML workflow file and lowers it into main.workflow definitions.
+/// Constructs, which are lowered not exactly, are added to `warnings`.
+pub fn parse(source: &Arc<Source>, warnings: &mut Diagnostics) -> Result<Vec<Def>, Diagnostics> {
+    let file: WorkflowFile = serde_yaml::from_str(&source.text).map_err(|err| {
+        let span = match err.location() {
+            Some(loc) => Span::new(source, loc.index()..loc.index()),
+            None => Span::file(source),
+        };
+        Diagnostic::at(span, err.to_string())
+    })?;
+    let workflow_name = file.name.clone().unwrap_or_else(|| file_stem(source));
+    let mut lowering = Lowering {
+        source,
+        workflow_name: &workflow_name,
+        workflow_env: &file.env,
+        jobs: &file.jobs,
+        defs: vec![],
+        tails: HashMap::new(),
+        in_progress: vec![],
+        diags: Diagnostics::new(),
+        warnings: Diagnostics::new(),
+    };
+    let on = lower_on(source, &file.on, &mut lowering.warnings).unwrap_or_else(|msg| {
+        lowering.error(msg);
+        vec![]
+    });
+    let mut resolves = vec![];
+    for job_id in file.jobs.keys() {
+        for tail in lowering.lower_job(job_id) {
+            if !resolves.contains(&tail) {
+                resolves.push(tail);
+            }
+        }
+    }
+    let Lowering {
+        mut defs,
+        diags,
+        warnings: lowering_warnings,
+        ..
+    } = lowering;
+    warnings.append(lowering_warnings);
+    defs.push(Def::Workflow(WorkflowDef {
+        name: workflow_name.clone(),
+        on,
+        resolves,
+        yaml: true,
+        spans: DefSpans::new(Span::file(source)),
+    }));
+    diags.finish(defs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::{self, parser, Event, Hir};
+
+    fn lower(src: &str) -> Result<Hir, Diagnostics> {
+        hir::build(parse(
+            &Source::new("ci.yml", src.to_string()),
+            &mut Diagnostics::new(),
+        )?)
+    }
+
+    #[test]
+    fn yaml_only_events() {
+        let h = lower(
+            "on: [pull_request_target, workflow_dispatch]\njobs:\n  a:\n    container: alpine\n    steps:\n      - run: true\n",
+        )
+        .unwrap();
+        let wf = h.find_workflow("ci").unwrap();
+        assert!(matches!(wf.on[0], Event::PullRequestTarget));
+        assert!(matches!(wf.on[1], Event::WorkflowDispatch));
+        assert!(wf.is_triggered_by("workflow_dispatch"));
+
+        let hcl = "workflow \"ci\" {\n  on = \"workflow_dispatch\"\n  resolves = []\n}\n";
+        let defs = parser::parse(&Source::new("main.workflow", hcl.to_string())).unwrap();
+        let err = hir::build(defs).unwrap_err().to_string();
+        assert!(
+            err.contains("workflow trigger workflow_dispatch is only available in YAML workflows"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn args_are_split_like_shell() {
+        assert_eq!(
+            split_args("  -v  --name x ").unwrap(),
+            ["-v", "--name", "x"]
+        );
+        assert_eq!(
+            split_args(r#"say "hello world" 'it''s' a\ b "" '\n' "\"\q""#).unwrap(),
+            ["say", "hello world", "its", "a b", "", "\\n", "\"\\q"]
+        );
+        assert!(split_args("").unwrap().is_empty());
+        assert_eq!(
+            split_args("'open").unwrap_err(),
+            "args have unterminated single quote"
+        );
+        assert_eq!(
+            split_args("\"open\\\"").unwrap_err(),
+            "args have unterminated double quote"
+        );
+        assert_eq!(split_args("end\\").unwrap_err(), "args end with backslash");
+    }
+
+    #[test]
+    fn malformed_args_are_reported() {
+        let err = lower(
+            "on: push\njobs:\n  a:\n    steps:\n      - uses: owner/repo@v1\n        with:\n          args: echo 'hi\n",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("ci/a/step-1: args have unterminated single quote"),
+            "{}",
+            err
+        );
+    }
+
+    fn action<'a>(h: &'a Hir, name: &str) -> &'a hir::Action {
+        h.action(h.find_action(name).unwrap())
+    }
+
+    fn needs(h: &Hir, name: &str) -> Vec<String> {
+        action(h, name)
+            .needs
+            .iter()
+            .map(|&dep| h.action(dep).name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn jobs_and_steps() {
+        let h = lower(
+            r#"
+name: CI
+on: push
+jobs:
+  build:
+    runs-on: ubuntu-22.04
+    steps:
+      - uses: actions/checkout@v4
+      - name: compile
+        run: make
+      - id: check
+        run: make check
+        working-directory: src
+  deploy:
+    needs: [build, lint]
+    container:
+      image: alpine
+    steps:
+      - run: ./deploy.sh
+  lint:
+    container: rust
+    steps:
+      - run: cargo clippy
+      - run: cargo fmt --check
+"#,
+        )
+        .unwrap();
+        let names: Vec<_> = h.actions().map(|act| act.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "CI/build/compile",
+                "CI/build/check",
+                "CI/lint/step-1",
+                "CI/lint/step-2",
+                "CI/deploy/step-1",
+            ]
+        );
+        assert!(needs(&h, "CI/build/compile").is_empty());
+        assert_eq!(needs(&h, "CI/build/check"), ["CI/build/compile"]);
+        assert_eq!(needs(&h, "CI/lint/step-2"), ["CI/lint/step-1"]);
+        assert_eq!(
+            needs(&h, "CI/deploy/step-1"),
+            ["CI/build/check", "CI/lint/step-2"]
+        );
+
+        let wf = h.find_workflow("CI").unwrap();
+        let resolves: Vec<_> = wf
+            .depends
+            .iter()
+            .map(|&act| h.action(act).name.as_str())
+            .collect();
+        assert_eq!(
+            resolves,
+            ["CI/build/check", "CI/deploy/step-1", "CI/lint/step-2"]
+        );
+
+        let check = action(&h, "CI/build/check");
+        assert_eq!(check.uses, "docker://ubuntu:22.04");
+        assert_eq!(
+            check.runs.as_ref().unwrap(),
+            &[
+                "bash",
+                "--noprofile",
+                "--norc",
+                "-eo",
+                "pipefail",
+                "-c",
+                "cd 'src'\nmake check"
+            ]
+        );
+        let deploy = action(&h, "CI/deploy/step-1");
+        assert_eq!(deploy.uses, "docker://alpine");
+        assert_eq!(
+            deploy.runs.as_ref().unwrap(),
+            &["sh", "-e", "-c", "./deploy.sh"]
+        );
+        assert_eq!(action(&h, "CI/lint/step-1").uses, "docker://rust");
+    }
+
+    #[test]
+    fn uses_with_inputs() {
+        let h = lower(
+            r#"
+on: push
+jobs:
+  a:
+    steps:
+      - uses: owner/repo/path@v1
+        with:
+          entrypoint: /bin/sh
+          args: -c "echo hi"
+          node version: 12
+"#,
+        )
+        .unwrap();
+        let act = action(&h, "ci/a/step-1");
+        assert_eq!(act.uses, "owner/repo/path@v1");
+        assert_eq!(act.runs.as_ref().unwrap(), &["/bin/sh"]);
+        assert_eq!(act.args.as_ref().unwrap(), &["-c", "echo hi"]);
+        assert_eq!(act.env["INPUT_NODE_VERSION"], "12");
+    }
+
+    #[test]
+    fn env_and_secrets() {
+        let h = lower(
+            r#"
+on: push
+env:
+  LEVEL: workflow
+  TOKEN: plain
+jobs:
+  a:
+    container: alpine
+    env:
+      LEVEL: job
+      TOKEN: ${{ secrets.TOKEN }}
+    steps:
+      - run: "true"
+        env:
+          STEP: 1
+      - run: "true"
+        env:
+          LEVEL: step
+          TOKEN: visible
+"#,
+        )
+        .unwrap();
+        let first = action(&h, "ci/a/step-1");
+        assert_eq!(first.env["LEVEL"], "job");
+        assert_eq!(first.env["STEP"], "1");
+        assert!(!first.env.contains_key("TOKEN"));
+        assert_eq!(first.secrets, ["TOKEN"]);
+        let second = action(&h, "ci/a/step-2");
+        assert_eq!(second.env["LEVEL"], "step");
+        assert_eq!(second.env["TOKEN"], "visible");
+        assert!(second.secrets.is_empty());
+    }
+
+    #[test]
+    fn rejected_constructs() {
+        let cases = &[
+            (
+                "a:\n    container: alpine\n    steps:\n      - run: x\n        uses: owner/repo@v1\n",
+                "ci/a/step-1: both `uses` and `run` are set",
+            ),
+            (
+                "a:\n    container: alpine\n    steps:\n      - name: nothing\n",
+                "ci/a/nothing: neither `uses` nor `run` is set",
+            ),
+            (
+                "a:\n    uses: owner/repo/.github/workflows/ci.yml@v1\n",
+                "job a: reusable workflows are not supported",
+            ),
+            (
+                "a:\n    needs: b\n    container: alpine\n    steps:\n      - run: x\n",
+                "job a needs unknown job b",
+            ),
+            (
+                "a:\n    needs: b\n    steps: []\n  b:\n    needs: a\n    steps: []\n",
+                "jobs are cycled: a -> b -> a",
+            ),
+            (
+                "a:\n    runs-on: macos-latest\n    steps:\n      - run: x\n",
+                "job a: runs-on has no docker image equivalent, please set `container`",
+            ),
+            (
+                "a:\n    container: alpine\n    steps:\n      - run: x\n        shell: pwsh\n",
+                "ci/a/step-1: shell pwsh is not supported",
+            ),
+            (
+                "a:\n    container: alpine\n    env:\n      KEY: ${{ secrets.OTHER }}\n    steps:\n      - run: x\n",
+                "ci/a/step-1: env var KEY refers to secret OTHER",
+            ),
+        ];
+        for (jobs, expected) in cases {
+            let err = lower(&format!("on: push\njobs:\n  {}", jobs))
+                .unwrap_err()
+                .to_string();
+            assert!(
+                err.contains(expected),
+                "expected {:?} in:\n{}",
+                expected,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn inexact_constructs_are_warned() {
+        let src = r#"
+on:
+  push:
+    branches: [master]
+jobs:
+  a:
+    container: alpine
+    if: github.ref == 'refs/heads/master'
+    strategy:
+      matrix:
+        n: [1, 2]
+    steps:
+      - run: echo $N
+        if: always()
+        env:
+          N: ${{ matrix.n }}
+"#;
+        let mut warnings = Diagnostics::new();
+        parse(&Source::new("ci.yml", src.to_string()), &mut warnings).unwrap();
+        let text = warnings.warnings().to_string();
+        assert_eq!(warnings.len(), 5, "{}", text);
+        for expected in &[
+            "warning: ci.yml: filters of event push are ignored",
+            "warning: ci.yml: job a: `if` is not supported, job will always run",
+            "warning: ci.yml: job a: `strategy` is not supported and ignored",
+            "warning: ci.yml: ci/a/step-1: `if` is not supported, step will always run",
+            "warning: ci.yml: ci/a/step-1: expressions are not evaluated in env var N",
+        ] {
+            assert!(text.contains(expected), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! Runs GitHub Actions workflows locally, in docker or podman containers.
+//!
+//! Workflow files are parsed with [`hir::parser::parse`] (or [`hir::load`] for the whole
+//! repository), checked and resolved by [`hir::build`], ordered by [`scheduler::schedule`]
+//! and executed action-by-action with [`execute::execute`].
+#[macro_use]
+extern crate pest_derive;
+
+pub mod convert;
+pub mod execute;
+pub mod fmt;
+pub mod graph;
+pub mod hir;
+pub mod scheduler;
+pub mod secrets;
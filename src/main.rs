@@ -1,80 +1,503 @@
-#[macro_use]
-extern crate pest_derive;
-
-mod execute;
-mod hir;
-mod scheduler;
-
+use gh_actions_local_runner::{
+    convert, execute,
+    execute::{
+        backend::{Docker, Podman},
+        Outcome,
+    },
+    fmt, graph,
+    hir::{
+        self,
+        diag::{Diagnostics, Source},
+    },
+    scheduler, secrets,
+};
 use std::{
+    ffi::OsString,
     path::{Path, PathBuf},
     process::exit,
+    sync::Arc,
 };
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
-struct Opts {
+#[structopt(after_help = "Without subcommand, arguments are passed to `run`.")]
+// parsed once, so size of variants does not matter
+#[allow(clippy::large_enum_variant)]
+enum Opts {
+    /// Runs workflows locally
+    #[structopt(name = "run")]
+    Run(RunOpts),
+    /// Converts main.workflow into YAML workflows in .github/workflows
+    #[structopt(name = "convert")]
+    Convert(ConvertOpts),
+    /// Rewrites main.workflow in canonical layout
+    #[structopt(name = "fmt")]
+    Fmt(FmtOpts),
+    /// Checks workflows for errors without running them
+    #[structopt(name = "validate", alias = "lint")]
+    Validate(RepoOpts),
+    /// Prints workflows and their actions in order they would run
+    #[structopt(name = "list")]
+    List(RepoOpts),
+    /// Prints dependency graph of workflows and actions
+    #[structopt(name = "graph")]
+    Graph(GraphOpts),
+}
+
+#[derive(StructOpt)]
+struct GraphOpts {
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+    /// Output format: dot, mermaid or json
+    #[structopt(long = "format", default_value = "dot")]
+    format: graph::Format,
+    /// Highlight action and actions it needs (can be repeated)
+    #[structopt(long = "target", number_of_values = 1)]
+    target: Vec<String>,
+}
+
+#[derive(StructOpt)]
+struct RepoOpts {
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+}
+
+#[derive(StructOpt)]
+struct FmtOpts {
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+    /// Do not write file; exit with error if it is not formatted
+    #[structopt(long = "check")]
+    check: bool,
+}
+
+#[derive(StructOpt)]
+struct ConvertOpts {
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+    /// Overwrite existing files
+    #[structopt(short = "f", long = "force")]
+    force: bool,
+}
+
+#[derive(StructOpt)]
+struct RunOpts {
+    #[structopt(parse(from_os_str))]
     path: PathBuf,
     #[structopt(short = "d", long = "dry")]
     dry_run: bool,
+    /// Directory with git mirrors of used actions, laid out as `<owner>/<repo>.git`
+    #[structopt(long = "actions-cache", parse(from_os_str))]
+    actions_cache: Option<PathBuf>,
+    /// Never access network; fail if action repository is not cached
+    #[structopt(long = "offline")]
+    offline: bool,
+    /// Run only workflow with given name (by default all workflows triggered by event are run)
+    #[structopt(long = "workflow")]
+    workflow: Option<String>,
+    /// Name of event, which triggers workflow
+    #[structopt(long = "event", default_value = "push")]
+    event: String,
+    /// JSON file with event payload; by default push payload is generated from local repository
+    #[structopt(long = "event-payload", parse(from_os_str))]
+    event_payload: Option<PathBuf>,
+    /// File with secret values: JSON object or dotenv-style `NAME=value` lines
+    #[structopt(long = "secrets-file", parse(from_os_str))]
+    secrets_file: Option<PathBuf>,
+    /// Forward secret with given name from environment (can be repeated)
+    #[structopt(long = "secret", number_of_values = 1)]
+    secret: Vec<String>,
+    /// Maximum number of actions to run concurrently (defaults to number of CPUs)
+    #[structopt(short = "j", long = "jobs")]
+    jobs: Option<usize>,
+    /// Run only given action and actions it needs (can be repeated)
+    #[structopt(long = "target", number_of_values = 1)]
+    target: Vec<String>,
+    /// Run only given action, without actions it needs (can be repeated)
+    #[structopt(long = "only", number_of_values = 1, conflicts_with = "target")]
+    only: Vec<String>,
+    /// Do not run given action; actions which need it still run (can be repeated)
+    #[structopt(long = "skip", number_of_values = 1)]
+    skip: Vec<String>,
+    /// Container engine: docker or podman
+    #[structopt(
+        long = "backend",
+        default_value = "docker",
+        raw(possible_values = r#"&["docker", "podman"]"#)
+    )]
+    backend: String,
 }
 
-fn deep_copy_with_ignore(src: &Path, dest: &Path) {
-    let walker = ignore::Walk::new(src);
-    for item in walker {
-        let item = item.expect("failed process item");
-        let is_file = match item.file_type() {
-            Some(s) => s.is_file(),
-            None => false,
-        };
-        if !is_file {
-            continue;
+fn default_actions_cache() -> PathBuf {
+    let cache_root = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").unwrap_or_else(|| {
+                eprintln!("error: HOME is not set, please specify --actions-cache");
+                exit(1);
+            });
+            PathBuf::from(home).join(".cache")
         }
-        let path = item.path();
-        let rel_path = path.strip_prefix(src).expect("failed strip src path");
-        let dest_path = dest.join(rel_path);
-        std::fs::create_dir_all(dest_path.parent().unwrap()).expect("failed prepare dir for copy dest");
-        std::fs::copy(path, &dest_path).unwrap_or_else(|err| {
-            panic!("failed copy {} to {}: {}", path.display(), dest_path.display(), err)
+    };
+    cache_root.join("gh-actions-local-runner/repos")
+}
+
+fn alloc_temp_dir(purpose: &str) -> String {
+    tempfile::tempdir()
+        .unwrap_or_else(|err| panic!("failed allocate {} dir: {}", purpose, err))
+        .into_path()
+        .to_str()
+        .expect("system temp dir path is not utf8")
+        .to_string()
+}
+
+/// Returns workflows which should be run for the chosen event
+fn select_workflows<'a>(h: &'a hir::Hir, opts: &RunOpts) -> Vec<&'a hir::Workflow> {
+    if let Some(name) = &opts.workflow {
+        let wf = h.find_workflow(name).unwrap_or_else(|| {
+            eprintln!("error: workflow '{}' is not defined", name);
+            exit(1);
         });
+        if !wf.is_triggered_by(&opts.event) {
+            eprintln!(
+                "error: workflow '{}' is not triggered by {}",
+                name, &opts.event
+            );
+            exit(1);
+        }
+        return vec![wf];
+    }
+    let selected: Vec<_> = h
+        .workflows()
+        .filter(|wf| wf.is_triggered_by(&opts.event))
+        .collect();
+    if selected.is_empty() {
+        eprintln!("error: event {} does not trigger any workflow", &opts.event);
+        exit(1);
+    }
+    selected
+}
+
+/// Prints error and exits
+fn fail(err: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", err);
+    exit(1);
+}
+
+/// Prints all diagnostics and exits
+fn report(diags: Diagnostics) -> ! {
+    eprintln!("{}", diags);
+    if diags.len() > 1 {
+        eprintln!("error: found {} problems", diags.len());
+    }
+    exit(1);
+}
+
+/// Prints problems, which do not stop the command
+fn warn(diags: &Diagnostics) {
+    if !diags.is_empty() {
+        eprintln!("{}", diags.warnings());
+    }
+}
+
+/// Returns serialized payload of the event, which triggers workflow
+fn make_event_payload(opts: &RunOpts, git: &execute::GitInfo) -> String {
+    match &opts.event_payload {
+        Some(path) => {
+            let data = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                eprintln!("error: failed read {}: {}", path.display(), err);
+                exit(1);
+            });
+            if let Err(err) = serde_json::from_str::<serde_json::Value>(&data) {
+                eprintln!("error: {} is not valid JSON: {}", path.display(), err);
+                exit(1);
+            }
+            data
+        }
+        None => {
+            if opts.event != "push" {
+                eprintln!(
+                    "error: payload can only be generated for push event, please provide --event-payload for {}",
+                    &opts.event
+                );
+                exit(1);
+            }
+            let event = execute::event::push_payload(git);
+            serde_json::to_string_pretty(&event).expect("failed serialize event")
+        }
     }
 }
 
+fn read_hcl(repo: &Path) -> hir::Hir {
+    let path = repo.join(".github/main.workflow");
+    if !path.exists() {
+        eprintln!("error: {} does not exist", path.display());
+        exit(1);
+    }
+    let source = Source::read(&path).unwrap_or_else(|diag| report(diag.into()));
+    let mut diags = Diagnostics::new();
+    let defs = match hir::parser::parse_partial(&source, &mut diags) {
+        Some(defs) => defs,
+        None => report(diags),
+    };
+    let hir = hir::build_partial(defs, &mut diags);
+    diags.finish(hir).unwrap_or_else(|diags| report(diags))
+}
+
+fn convert(opts: ConvertOpts) {
+    let hir = read_hcl(&opts.path);
+    let out_dir = opts.path.join(".github/workflows");
+    let mut converted_actions = vec![false; hir.actions_cnt()];
+    let mut outputs = vec![];
+    for wf in hir.workflows() {
+        let order = scheduler::schedule(&hir, wf).unwrap_or_else(|diags| report(diags));
+        for act in &order {
+            converted_actions[act.as_inner()] = true;
+        }
+        let converted = convert::convert(&hir, wf, &order);
+        for warning in &converted.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        let path = out_dir.join(&converted.file_name);
+        if outputs.iter().any(|(p, _)| p == &path) {
+            eprintln!(
+                "error: several workflows are converted to {}",
+                path.display()
+            );
+            exit(1);
+        }
+        if path.exists() && !opts.force {
+            eprintln!(
+                "error: {} already exists, use --force to overwrite it",
+                path.display()
+            );
+            exit(1);
+        }
+        outputs.push((path, converted.yaml));
+    }
+    for (i, converted) in converted_actions.into_iter().enumerate() {
+        if !converted {
+            let act = hir.action(hir::ActionId::new(i));
+            eprintln!(
+                "warning: action '{}' is not resolved by any workflow and is dropped",
+                &act.name
+            );
+        }
+    }
+    std::fs::create_dir_all(&out_dir).expect("failed create workflows dir");
+    for (path, yaml) in outputs {
+        std::fs::write(&path, yaml).expect("failed write workflow");
+        println!("wrote {}", path.display());
+    }
+}
+
+fn format(opts: FmtOpts) {
+    let path = opts.path.join(".github/main.workflow");
+    let source = Source::read(&path).unwrap_or_else(|diag| report(diag.into()));
+    let doc = hir::parser::parse_blocks(&source).unwrap_or_else(|diags| report(diags));
+    let formatted = fmt::format(&doc);
+    if formatted == source.text {
+        return;
+    }
+    if opts.check {
+        eprintln!("error: {} is not formatted", path.display());
+        exit(1);
+    }
+    std::fs::write(&path, formatted).unwrap_or_else(|err| {
+        eprintln!("error: failed write {}: {}", path.display(), err);
+        exit(1);
+    });
+}
+
+/// Names of subcommands and top-level flags
+const SUBCOMMANDS: &[&str] = &[
+    "run",
+    "convert",
+    "fmt",
+    "validate",
+    "lint",
+    "list",
+    "graph",
+    "help",
+    "-h",
+    "--help",
+    "-V",
+    "--version",
+];
+
+/// Returns command line arguments, inserting `run` if subcommand is omitted,
+/// so that `gh-actions-local-runner <path>` keeps working
+fn args() -> Vec<OsString> {
+    let mut args: Vec<_> = std::env::args_os().collect();
+    let has_subcommand = match args.get(1) {
+        Some(arg) => arg.to_str().is_some_and(|arg| SUBCOMMANDS.contains(&arg)),
+        None => true,
+    };
+    if !has_subcommand {
+        args.insert(1, "run".into());
+    }
+    args
+}
+
 fn main() {
-    let opts: Opts = Opts::from_args();
-    let workflow_path = opts.path.join(".github/main.workflow");
-    if !workflow_path.exists() {
-        eprintln!("error: {} does not exist", workflow_path.display());
+    match Opts::from_iter(args()) {
+        Opts::Run(opts) => run(opts),
+        Opts::Convert(opts) => convert(opts),
+        Opts::Fmt(opts) => format(opts),
+        Opts::Validate(opts) => validate(opts),
+        Opts::List(opts) => list(opts),
+        Opts::Graph(opts) => graph(opts),
+    }
+}
+
+/// Loads and checks workflows of the repository
+/// Loads workflows, warning about disabled YAML workflows unless `selected` is one of them
+fn load_hir(repo: &Path, selected: Option<&str>) -> hir::Hir {
+    let loaded = hir::load(repo).unwrap_or_else(|diags| report(diags));
+    warn(&loaded.warnings);
+    for wf in loaded.disabled {
+        if selected == Some(wf.name.as_str()) {
+            report(wf.diags);
+        }
+        eprintln!(
+            "warning: workflow '{}' from {} is disabled, because it can't be run locally",
+            &wf.name, &wf.path
+        );
+        warn(&wf.diags);
+    }
+    loaded.hir
+}
+
+fn validate(opts: RepoOpts) {
+    let loaded = hir::load(&opts.path).unwrap_or_else(|diags| report(diags));
+    warn(&loaded.warnings);
+    let mut diags = Diagnostics::new();
+    for wf in loaded.disabled {
+        diags.append(wf.diags);
+    }
+    if let Err(cycles) = scheduler::check_cycles(&loaded.hir) {
+        diags.append(cycles);
+    }
+    diags.finish(()).unwrap_or_else(|diags| report(diags));
+}
+
+fn list(opts: RepoOpts) {
+    let hir = load_hir(&opts.path, None);
+    for (i, wf) in hir.workflows().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        let on: Vec<_> = wf.on.iter().map(ToString::to_string).collect();
+        println!("workflow {} (on {})", &wf.name, on.join(", "));
+        let order = scheduler::schedule(&hir, wf).unwrap_or_else(|diags| report(diags));
+        let name_width = order
+            .iter()
+            .map(|&act| hir.action(act).name.len())
+            .max()
+            .unwrap_or(0);
+        for act in order {
+            let act = hir.action(act);
+            println!("  {:width$}  {}", &act.name, &act.uses, width = name_width);
+        }
+    }
+}
+
+fn graph(opts: GraphOpts) {
+    let hir = load_hir(&opts.path, None);
+    let highlight = if opts.target.is_empty() {
+        None
+    } else {
+        Some(graph::Highlight::new(&hir, &opts.target).unwrap_or_else(|err| fail(err)))
+    };
+    print!("{}", graph::render(&hir, opts.format, highlight.as_ref()));
+}
+
+fn run(opts: RunOpts) {
+    let hir = load_hir(&opts.path, opts.workflow.as_deref());
+    if !hir::Event::is_known_name(&opts.event) {
+        eprintln!("error: unknown event {}", &opts.event);
         exit(1);
     }
-    let workflow_data = std::fs::read_to_string(workflow_path).expect("failed read workflow");
-    let defs = hir::parser::parse(&workflow_data);
-    let hir = hir::build(defs);
-    let actions_in_order = scheduler::schedule(&hir);
+    let selection = scheduler::Selection::new(&hir, &opts.target, &opts.only, &opts.skip)
+        .unwrap_or_else(|err| fail(err));
+    let workflows: Vec<_> = select_workflows(&hir, &opts)
+        .into_iter()
+        .map(|wf| {
+            let order = scheduler::schedule(&hir, wf).unwrap_or_else(|diags| report(diags));
+            (wf, selection.apply(&order))
+        })
+        .filter(|(_, order)| !order.is_empty())
+        .collect();
+    let all_actions: Vec<_> = workflows
+        .iter()
+        .flat_map(|(_, actions)| actions.iter().copied())
+        .collect();
+    selection
+        .check(&hir, &all_actions)
+        .unwrap_or_else(|err| fail(err));
 
-    let workspace_dir = tempfile::tempdir().expect("failed allocate workspace dir");
-    let home_dir = tempfile::tempdir().expect("failed allocate home dir");
-    let workspace_dir = workspace_dir
-        .into_path()
-        .to_str()
-        .expect("system temp dir path is not utf8")
-        .to_string();
-    let home_dir = home_dir
-        .into_path()
-        .to_str()
-        .expect("system temp dir path is not utf8")
-        .to_string();
+    let secrets = secrets::Secrets::load(opts.secrets_file.as_deref(), &opts.secret)
+        .unwrap_or_else(|err| fail(err));
+    secrets
+        .check(&hir, &all_actions)
+        .unwrap_or_else(|err| fail(err));
 
-    deep_copy_with_ignore(&opts.path, Path::new(&workspace_dir));
+    let workflow_dir = alloc_temp_dir("workflow");
+    let git = execute::get_git_info(&opts.path).unwrap_or_else(|err| fail(err));
+    let event = make_event_payload(&opts, &git);
+    std::fs::write(Path::new(&workflow_dir).join("event.json"), event)
+        .expect("failed write event payload");
 
-    let ex_opts = execute::ExecOptions {
+    let mut ex_opts = execute::ExecOptions {
         dry: opts.dry_run,
-        dir: opts.path.clone(),
-        git: execute::get_git_info(&opts.path),
-        home_dir,
-        workspace_dir,
+        git,
+        home_dir: String::new(),
+        workspace_dir: String::new(),
+        workflow_dir,
+        secrets,
+        event_name: opts.event.clone(),
+        actions_cache: opts
+            .actions_cache
+            .clone()
+            .unwrap_or_else(default_actions_cache),
+        offline: opts.offline,
+        mirror_locks: execute::MirrorLocks::new(),
+        backend: match opts.backend.as_str() {
+            "docker" => Arc::new(Docker::new(opts.dry_run)),
+            "podman" => Arc::new(Podman::new(opts.dry_run)),
+            _ => unreachable!("checked by structopt"),
+        },
     };
 
-    for act in actions_in_order {
-        execute::execute(&hir, act, &ex_opts);
+    let jobs = opts.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    if jobs == 0 {
+        eprintln!("error: --jobs must be positive");
+        exit(1);
+    }
+    let mut failed = false;
+    for (wf, actions_in_order) in workflows {
+        println!("===running workflow {}===", &wf.name);
+        ex_opts.home_dir = alloc_temp_dir("home");
+        // every workflow run starts with fresh workspace
+        ex_opts.workspace_dir = alloc_temp_dir("workspace");
+        execute::copy_workspace(&opts.path, Path::new(&ex_opts.workspace_dir))
+            .unwrap_or_else(|err| fail(err));
+
+        let progress = scheduler::run(&hir, &actions_in_order, jobs, |act| {
+            println!("---executing action {}---", &hir.action(act).name);
+            execute::execute(&hir, wf, act, &ex_opts).unwrap_or_else(|err| {
+                eprintln!("[{}] error: {}", &hir.action(act).name, err);
+                Outcome::Failure
+            })
+        });
+        progress.print_summary(&actions_in_order);
+        failed |= progress.failed();
+    }
+    if failed {
+        exit(1);
     }
 }
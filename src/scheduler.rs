@@ -1,97 +1,835 @@
-use crate::hir::{ActionId, Hir};
+use crate::{
+    execute::Outcome,
+    hir::{
+        diag::{Diagnostic, Diagnostics},
+        ActionId, Hir, Workflow,
+    },
+};
+use std::{panic, sync::mpsc};
+
+/// Limit on number of reported cycles, because it may grow exponentially with graph size
+const MAX_CYCLES: usize = 100;
+
+/// Dependency graph of actions, with edges from action to actions it needs
+pub struct Graph {
+    adj: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    pub fn new(h: &Hir) -> Graph {
+        let adj = h
+            .actions()
+            .map(|act| {
+                let mut needs = vec![];
+                for dep in &act.needs {
+                    // repeated `needs` would produce repeated cycles
+                    if !needs.contains(&dep.as_inner()) {
+                        needs.push(dep.as_inner());
+                    }
+                }
+                needs
+            })
+            .collect();
+        Graph { adj }
+    }
+
+    pub fn needs(&self, act: ActionId) -> impl Iterator<Item = ActionId> + '_ {
+        self.adj[act.as_inner()].iter().copied().map(ActionId::new)
+    }
+
+    /// Returns mask of actions, which `roots` need directly or transitively
+    /// (roots themselves included)
+    pub fn closure(&self, roots: &[ActionId]) -> Vec<bool> {
+        let mut reached = vec![false; self.adj.len()];
+        let mut stack: Vec<_> = roots.iter().map(ActionId::as_inner).collect();
+        while let Some(v) = stack.pop() {
+            if reached[v] {
+                continue;
+            }
+            reached[v] = true;
+            stack.extend(&self.adj[v]);
+        }
+        reached
+    }
+
+    /// Returns elementary cycles among actions, marked in `within` mask.
+    /// The mask must be closed under `needs`, like masks returned by `closure`.
+    ///
+    /// Each action of a cycle needs the next one, and the last action needs the first.
+    /// Cycle starts from the action which was defined first.
+    pub fn cycles(&self, within: &[bool]) -> Cycles {
+        let mut found = Cycles {
+            cycles: vec![],
+            truncated: false,
+        };
+        // mask is closed, so components of the whole graph are components of the masked part
+        for scc in Tarjan::components(&self.adj) {
+            if !within[scc[0]] {
+                continue;
+            }
+            // cycles through `start` only use actions, defined after it,
+            // so every cycle is found exactly once
+            let mut allowed = vec![false; self.adj.len()];
+            for &v in &scc {
+                allowed[v] = true;
+            }
+            for &start in &scc {
+                let mut search = CycleSearch {
+                    adj: &self.adj,
+                    start,
+                    allowed: &allowed,
+                    blocked: vec![false; self.adj.len()],
+                    blocked_by: vec![vec![]; self.adj.len()],
+                    path: vec![],
+                    found: &mut found,
+                };
+                search.circuit(start);
+                if found.truncated {
+                    break;
+                }
+                allowed[start] = false;
+            }
+        }
+        found
+            .cycles
+            .sort_by_key(|cycle| cycle.iter().map(ActionId::as_inner).collect::<Vec<_>>());
+        found
+    }
+}
+
+/// Elementary cycles of the graph
+pub struct Cycles {
+    cycles: Vec<Vec<ActionId>>,
+    /// There are more than `MAX_CYCLES` cycles, and only first of them are kept
+    truncated: bool,
+}
+
+impl Cycles {
+    fn push(&mut self, cycle: &[usize]) {
+        if self.cycles.len() == MAX_CYCLES {
+            self.truncated = true;
+        } else {
+            self.cycles
+                .push(cycle.iter().copied().map(ActionId::new).collect());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[ActionId]> {
+        self.cycles.iter().map(Vec::as_slice)
+    }
+
+    /// Checks whether some cycles were dropped because of `MAX_CYCLES` limit
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// Tarjan's algorithm for strongly connected components
+struct Tarjan<'a> {
+    adj: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    next_index: usize,
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    components: Vec<Vec<usize>>,
+}
+
+impl<'a> Tarjan<'a> {
+    /// Returns components, which contain at least one cycle, with sorted vertices,
+    /// ordered by their first vertex
+    fn components(adj: &'a [Vec<usize>]) -> Vec<Vec<usize>> {
+        let n = adj.len();
+        let mut t = Tarjan {
+            adj,
+            index: vec![None; n],
+            next_index: 0,
+            low: vec![0; n],
+            stack: vec![],
+            on_stack: vec![false; n],
+            components: vec![],
+        };
+        for v in 0..n {
+            if t.index[v].is_none() {
+                t.visit(v);
+            }
+        }
+        t.components.sort_unstable();
+        t.components
+    }
+
+    fn visit(&mut self, v: usize) {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.index[v] = Some(index);
+        self.low[v] = index;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+        for &w in &self.adj[v] {
+            match self.index[w] {
+                None => {
+                    self.visit(w);
+                    self.low[v] = self.low[v].min(self.low[w]);
+                }
+                Some(w_index) if self.on_stack[w] => {
+                    self.low[v] = self.low[v].min(w_index);
+                }
+                Some(_) => {}
+            }
+        }
+        if self.low[v] != index {
+            return;
+        }
+        let mut component = vec![];
+        loop {
+            let w = self.stack.pop().expect("v is on stack");
+            self.on_stack[w] = false;
+            component.push(w);
+            if w == v {
+                break;
+            }
+        }
+        if component.len() > 1 || self.adj[v].contains(&v) {
+            component.sort_unstable();
+            self.components.push(component);
+        }
+    }
+}
+
+/// Johnson's algorithm: enumerates simple paths from `start`, which return to it.
+///
+/// Action is blocked while it is on the path or can not reach `start` without
+/// crossing the path, so the search does not explore dead ends repeatedly.
+struct CycleSearch<'a> {
+    adj: &'a [Vec<usize>],
+    start: usize,
+    allowed: &'a [bool],
+    blocked: Vec<bool>,
+    /// Actions, which get unblocked together with the action
+    blocked_by: Vec<Vec<usize>>,
+    path: Vec<usize>,
+    found: &'a mut Cycles,
+}
+
+impl CycleSearch<'_> {
+    fn unblock(&mut self, v: usize) {
+        self.blocked[v] = false;
+        for w in std::mem::take(&mut self.blocked_by[v]) {
+            if self.blocked[w] {
+                self.unblock(w);
+            }
+        }
+    }
+
+    /// Returns whether any cycle passes through `v`
+    fn circuit(&mut self, v: usize) -> bool {
+        let mut closed = false;
+        self.path.push(v);
+        self.blocked[v] = true;
+        for &w in &self.adj[v] {
+            if self.found.truncated {
+                break;
+            }
+            if w == self.start {
+                self.found.push(&self.path);
+                closed = true;
+            } else if self.allowed[w] && !self.blocked[w] && self.circuit(w) {
+                closed = true;
+            }
+        }
+        if closed {
+            self.unblock(v);
+        } else {
+            for &w in &self.adj[v] {
+                if self.allowed[w] && !self.blocked_by[w].contains(&v) {
+                    self.blocked_by[w].push(v);
+                }
+            }
+        }
+        self.path.pop();
+        closed
+    }
+}
+
+/// Reports each cycle at definition of its first action, with `needs` of every action as notes
+fn cycle_diagnostics(h: &Hir, cycles: &Cycles) -> Diagnostics {
+    let mut diags = Diagnostics::new();
+    for cycle in cycles.iter() {
+        let mut names: Vec<_> = cycle.iter().map(|&v| h.action(v).name.as_str()).collect();
+        names.push(names[0]);
+        let first = h.action(cycle[0]);
+        let mut diag = Diagnostic::at(
+            first.spans.def.clone(),
+            format!("actions are cycled: {}", names.join(" -> ")),
+        );
+        for (i, &u) in cycle.iter().enumerate() {
+            let u = h.action(u);
+            let v = h.action(cycle[(i + 1) % cycle.len()]);
+            diag = diag.with_note(
+                u.spans.key("needs"),
+                format!("action '{}' needs '{}'", &u.name, &v.name),
+            );
+        }
+        diags.push(diag);
+    }
+    if cycles.is_truncated() {
+        diags.push(Diagnostic::new(format!(
+            "only first {} cycles are reported",
+            MAX_CYCLES
+        )));
+    }
+    diags
+}
+
+/// Reports all dependency cycles among actions
+pub fn check_cycles(h: &Hir) -> Result<(), Diagnostics> {
+    let g = Graph::new(h);
+    cycle_diagnostics(h, &g.cycles(&vec![true; g.adj.len()])).finish(())
+}
 
 #[derive(Copy, Clone)]
 enum Vis {
     Unvis,
-    InProg(usize),
+    InProg,
     Done,
 }
 
+/// Topological sort; fails on the first cycle found
 struct Helper<'a> {
     vis: Vec<Vis>,
     order: Vec<usize>,
     g: &'a [Vec<usize>],
-    stack: Vec<usize>,
-    hir: &'a Hir,
 }
 
 impl<'a> Helper<'a> {
-    fn new(h: &'a Hir, g: &'a [Vec<usize>]) -> Helper<'a> {
-        let n = h.actions_cnt();
+    fn new(g: &'a [Vec<usize>]) -> Helper<'a> {
+        let n = g.len();
 
         Helper {
             g,
             vis: vec![Vis::Unvis; n],
             order: Vec::with_capacity(n),
-            stack: Vec::with_capacity(n),
-            hir: h,
-        }
-    }
-    fn print_cycle(&self, cycle_top: usize) {
-        let stack_size = self.stack.len();
-        let cycle_len = stack_size - cycle_top;
-        for i in 0..cycle_len {
-            let u_pos = cycle_top + i;
-            let v_pos = if i < cycle_len - 1 {
-                cycle_top + i + 1
-            } else {
-                cycle_top
-            };
-            let u = ActionId::new(self.stack[u_pos]);
-            let v = ActionId::new(self.stack[v_pos]);
-            let u = self.hir.action(u);
-            let v = self.hir.action(v);
-            println!("{} -> {}", &u.name, &v.name);
         }
     }
 
-    fn dfs(&mut self, v: usize) {
-        self.vis[v] = Vis::InProg(self.stack.len());
-        self.stack.push(v);
+    fn dfs(&mut self, v: usize) -> Result<(), ()> {
+        self.vis[v] = Vis::InProg;
         for &w in &self.g[v] {
             match self.vis[w] {
                 Vis::Done => {}
-                Vis::InProg(j) => {
-                    eprintln!("actions are cycled");
-                    self.print_cycle(j);
-                    std::process::exit(1);
-                }
-                Vis::Unvis => {
-                    self.dfs(w);
-                }
+                Vis::InProg => return Err(()),
+                Vis::Unvis => self.dfs(w)?,
             }
         }
         self.order.push(v);
         self.vis[v] = Vis::Done;
-        self.stack.pop();
+        Ok(())
     }
 
-    fn run_all(&mut self) {
-        for i in 0..self.g.len() {
+    fn run_from(&mut self, roots: &[ActionId]) -> Result<(), ()> {
+        for root in roots {
+            let i = root.as_inner();
             match self.vis[i] {
-                Vis::Unvis => self.dfs(i),
-                Vis::InProg(_) => unreachable!(),
+                Vis::Unvis => self.dfs(i)?,
+                Vis::InProg => unreachable!(),
                 Vis::Done => {}
             }
         }
+        Ok(())
+    }
+}
+
+/// Returns actions resolved by the workflow (including transitive `needs`)
+/// in order they should be executed, or reports all cycles among them
+pub fn schedule(h: &Hir, wf: &Workflow) -> Result<Vec<ActionId>, Diagnostics> {
+    let g = Graph::new(h);
+    let mut helper = Helper::new(&g.adj);
+    if helper.run_from(&wf.depends).is_err() {
+        let cycles = g.cycles(&g.closure(&wf.depends));
+        debug_assert!(!cycles.is_empty(), "topological sort found a cycle");
+        return Err(cycle_diagnostics(h, &cycles));
+    }
+
+    Ok(helper.order.into_iter().map(ActionId::new).collect())
+}
+
+/// Actions, chosen to run on command line.
+///
+/// `targets` are run together with actions they need, and `only` without them.
+/// If both are empty, all actions are chosen. `skip` actions are never run.
+pub struct Selection {
+    chosen: Vec<bool>,
+    skipped: Vec<bool>,
+    /// Actions, which must run in some workflow
+    required: Vec<ActionId>,
+}
+
+impl Selection {
+    pub fn new(
+        h: &Hir,
+        targets: &[String],
+        only: &[String],
+        skip: &[String],
+    ) -> Result<Selection, String> {
+        let find = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| {
+                    h.find_action(name)
+                        .ok_or_else(|| format!("action '{}' is not defined", name))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let (targets, only) = (find(targets)?, find(only)?);
+        let chosen = if targets.is_empty() && only.is_empty() {
+            vec![true; h.actions_cnt()]
+        } else {
+            let mut chosen = Graph::new(h).closure(&targets);
+            for act in &only {
+                chosen[act.as_inner()] = true;
+            }
+            chosen
+        };
+        let mut skipped = vec![false; h.actions_cnt()];
+        for act in find(skip)? {
+            skipped[act.as_inner()] = true;
+        }
+        let mut required = targets;
+        required.extend(only);
+        Ok(Selection {
+            chosen,
+            skipped,
+            required,
+        })
     }
+
+    /// Restricts scheduled actions to the chosen ones, keeping their order
+    pub fn apply(&self, order: &[ActionId]) -> Vec<ActionId> {
+        order
+            .iter()
+            .copied()
+            .filter(|act| self.chosen[act.as_inner()] && !self.skipped[act.as_inner()])
+            .collect()
+    }
+
+    /// Checks that every target and `only` action is among `selected` actions
+    pub fn check(&self, h: &Hir, selected: &[ActionId]) -> Result<(), String> {
+        match self.required.iter().find(|act| !selected.contains(act)) {
+            Some(&act) => Err(format!(
+                "action '{}' is not run by any selected workflow",
+                &h.action(act).name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Tracks outcomes of executed actions.
+///
+/// Action may only run if all its dependencies succeeded. Otherwise it is
+/// skipped, so failures and neutral outcomes propagate along `needs` edges.
+/// Dependencies, which are not scheduled, are assumed to be satisfied.
+pub struct Progress<'a> {
+    hir: &'a Hir,
+    scheduled: Vec<bool>,
+    outcomes: Vec<Option<Outcome>>,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(hir: &'a Hir, order: &[ActionId]) -> Progress<'a> {
+        let mut scheduled = vec![false; hir.actions_cnt()];
+        for act in order {
+            scheduled[act.as_inner()] = true;
+        }
+        Progress {
+            hir,
+            scheduled,
+            outcomes: vec![None; hir.actions_cnt()],
+        }
+    }
+
+    /// Returns dependency which prevents action from running, if any
+    pub fn blocker(&self, act: ActionId) -> Option<ActionId> {
+        self.hir.action(act).needs.iter().copied().find(|dep| {
+            self.scheduled[dep.as_inner()]
+                && self.outcomes[dep.as_inner()] != Some(Outcome::Success)
+        })
+    }
+
+    /// Checks that all scheduled dependencies of the action are finished
+    fn ready(&self, act: ActionId) -> bool {
+        self.hir
+            .action(act)
+            .needs
+            .iter()
+            .all(|dep| !self.scheduled[dep.as_inner()] || self.outcomes[dep.as_inner()].is_some())
+    }
+
+    /// Returns outcome of the action, `None` if it did not finish or was not scheduled
+    pub fn outcome(&self, act: ActionId) -> Option<Outcome> {
+        self.outcomes[act.as_inner()]
+    }
+
+    pub fn record(&mut self, act: ActionId, outcome: Outcome) {
+        self.outcomes[act.as_inner()] = Some(outcome);
+    }
+
+    /// Workflow fails if any action failed. Neutral and skipped actions do not fail it.
+    pub fn failed(&self) -> bool {
+        self.outcomes.contains(&Some(Outcome::Failure))
+    }
+
+    /// Prints table with outcome of each action
+    pub fn print_summary(&self, order: &[ActionId]) {
+        let name_width = order
+            .iter()
+            .map(|&act| self.hir.action(act).name.len())
+            .max()
+            .unwrap_or(0)
+            .max("ACTION".len());
+        println!("---summary---");
+        println!("{:width$}  OUTCOME", "ACTION", width = name_width);
+        for &act in order {
+            let outcome = match self.outcomes[act.as_inner()] {
+                Some(o) => o,
+                None => continue,
+            };
+            let mut line = format!(
+                "{:width$}  {}",
+                &self.hir.action(act).name,
+                outcome.as_str(),
+                width = name_width
+            );
+            if outcome == Outcome::Skipped {
+                if let Some(dep) = self.blocker(act) {
+                    let dep_outcome =
+                        self.outcomes[dep.as_inner()].map_or("pending", Outcome::as_str);
+                    line.push_str(&format!(
+                        " (needs {}: {})",
+                        &self.hir.action(dep).name,
+                        dep_outcome
+                    ));
+                }
+            }
+            println!("{}", line);
+        }
+    }
+}
+
+/// Executes scheduled actions, running up to `jobs` of them concurrently.
+///
+/// Action is started as soon as all its dependencies are finished.
+pub fn run<'a, F>(h: &'a Hir, order: &[ActionId], jobs: usize, exec: F) -> Progress<'a>
+where
+    F: Fn(ActionId) -> Outcome + Sync,
+{
+    let mut progress = Progress::new(h, order);
+    let mut started = vec![false; h.actions_cnt()];
+    let mut running = 0;
+    let (tx, rx) = mpsc::channel();
+    std::thread::scope(|s| loop {
+        for &act in order {
+            if started[act.as_inner()] || running >= jobs {
+                continue;
+            }
+            if !progress.ready(act) {
+                continue;
+            }
+            started[act.as_inner()] = true;
+            if let Some(dep) = progress.blocker(act) {
+                println!(
+                    "---skipping action {}: dependency {} did not succeed---",
+                    &h.action(act).name,
+                    &h.action(dep).name
+                );
+                progress.record(act, Outcome::Skipped);
+                continue;
+            }
+            running += 1;
+            let tx = tx.clone();
+            let exec = &exec;
+            s.spawn(move || {
+                // panic is forwarded to the scheduler thread, which would otherwise wait forever
+                let res = panic::catch_unwind(panic::AssertUnwindSafe(|| exec(act)));
+                // receiver lives until all actions are finished
+                tx.send((act, res)).ok();
+            });
+        }
+        if running == 0 {
+            // `order` is topologically sorted, so the pass above has started
+            // or skipped every remaining action
+            break;
+        }
+        let (act, res) = rx.recv().expect("all senders are alive");
+        let outcome = res.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        running -= 1;
+        progress.record(act, outcome);
+    });
+    progress
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::{self, diag::Source, parser};
+
+    fn load(src: &str) -> Hir {
+        let defs = parser::parse(&Source::new("main.workflow", src.to_string())).unwrap();
+        hir::build(defs).unwrap()
+    }
+
+    const INDEPENDENT: &str = r#"
+workflow "ci" {
+  on = "push"
+  resolves = ["a", "b", "c"]
+}
+
+action "a" {
+  uses = "docker://alpine"
+}
+
+action "b" {
+  uses = "docker://alpine"
 }
 
-pub fn schedule(h: &Hir) -> Vec<ActionId> {
-    let n = h.actions_cnt();
+action "c" {
+  uses = "docker://alpine"
+}
+"#;
+
+    #[test]
+    #[should_panic(expected = "executor failed")]
+    fn executor_panic_is_propagated() {
+        let h = load(INDEPENDENT);
+        let wf = h.workflows().next().unwrap();
+        let order = schedule(&h, wf).unwrap();
+        let b = h.find_action("b").unwrap();
+        run(&h, &order, 2, |act| {
+            if act == b {
+                panic!("executor failed");
+            }
+            Outcome::Success
+        });
+    }
 
-    let mut g = vec![vec![]; n];
-    for i in 0..n {
-        for &dep in &h.action(ActionId::new(i)).needs {
-            g[i].push(dep.as_inner())
+    /// Builds workflow `ci`, resolving `resolves`, from actions with their `needs`
+    fn graph(actions: &[(&str, &[&str])], resolves: &[&str]) -> Hir {
+        let quote = |names: &[&str]| -> String {
+            let names: Vec<_> = names.iter().map(|name| format!("\"{}\"", name)).collect();
+            names.join(", ")
+        };
+        let mut src = format!(
+            "workflow \"ci\" {{\n  on = \"push\"\n  resolves = [{}]\n}}\n",
+            quote(resolves)
+        );
+        for (name, needs) in actions {
+            src.push_str(&format!(
+                "\naction \"{}\" {{\n  uses = \"docker://alpine\"\n  needs = [{}]\n}}\n",
+                name,
+                quote(needs)
+            ));
         }
+        load(&src)
+    }
+
+    fn cycle_names(h: &Hir) -> Vec<String> {
+        let g = Graph::new(h);
+        g.cycles(&vec![true; h.actions_cnt()])
+            .iter()
+            .map(|cycle| {
+                let names: Vec<_> = cycle
+                    .iter()
+                    .map(|&act| h.action(act).name.as_str())
+                    .collect();
+                names.join(" ")
+            })
+            .collect()
+    }
+
+    fn schedule_errors(h: &Hir) -> Vec<String> {
+        let wf = h.workflows().next().unwrap();
+        let err = schedule(h, wf).unwrap_err().to_string();
+        err.lines()
+            .filter_map(|line| line.split("actions are cycled: ").nth(1))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn single_cycle() {
+        let h = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])], &["a"]);
+        assert_eq!(cycle_names(&h), ["a b c"]);
+        assert_eq!(schedule_errors(&h), ["a -> b -> c -> a"]);
+    }
+
+    #[test]
+    fn disjoint_cycles() {
+        let h = graph(
+            &[("a", &["b"]), ("b", &["a"]), ("c", &["d"]), ("d", &["c"])],
+            &["a", "c"],
+        );
+        assert_eq!(cycle_names(&h), ["a b", "c d"]);
+        assert_eq!(schedule_errors(&h), ["a -> b -> a", "c -> d -> c"]);
     }
-    let mut helper = Helper::new(h, &g);
-    helper.run_all();
 
-    let mut out = Vec::with_capacity(n);
-    for i in 0..n {
-        out.push(ActionId::new(helper.order[i]));
+    #[test]
+    fn nested_cycles() {
+        // both cycles pass through `a`, and the longer one contains the shorter one's edge
+        let h = graph(
+            &[("a", &["b", "c"]), ("b", &["a", "c"]), ("c", &["a"])],
+            &["a"],
+        );
+        assert_eq!(cycle_names(&h), ["a b", "a b c", "a c"]);
+    }
+
+    #[test]
+    fn self_loop() {
+        let h = graph(&[("a", &["a"]), ("b", &["a"])], &["b"]);
+        assert_eq!(cycle_names(&h), ["a"]);
+        assert_eq!(schedule_errors(&h), ["a -> a"]);
+    }
+
+    #[test]
+    fn repeated_needs() {
+        let h = graph(&[("a", &["b", "b"]), ("b", &["a"])], &["a"]);
+        assert_eq!(cycle_names(&h), ["a b"]);
+    }
+
+    /// Actions `k0`..`k<n>`, each needing all others
+    fn complete(n: usize) -> Vec<(String, Vec<String>)> {
+        (0..n)
+            .map(|i| {
+                let needs = (0..n).filter(|&j| j != i).map(|j| format!("k{}", j));
+                (format!("k{}", i), needs.collect())
+            })
+            .collect()
+    }
+
+    fn graph_owned(actions: &[(String, Vec<String>)], resolves: &[&str]) -> Hir {
+        let needs: Vec<Vec<&str>> = actions
+            .iter()
+            .map(|(_, needs)| needs.iter().map(String::as_str).collect())
+            .collect();
+        let actions: Vec<(&str, &[&str])> = actions
+            .iter()
+            .zip(&needs)
+            .map(|((name, _), needs)| (name.as_str(), needs.as_slice()))
+            .collect();
+        graph(&actions, resolves)
+    }
+
+    #[test]
+    fn cycles_limit() {
+        // complete graph on 5 actions has 84 cycles, on 6 actions 409
+        let h = graph_owned(&complete(5), &["k0"]);
+        let g = Graph::new(&h);
+        let cycles = g.cycles(&vec![true; h.actions_cnt()]);
+        assert_eq!(cycles.iter().count(), 84);
+        assert!(!cycles.is_truncated());
+
+        let h = graph_owned(&complete(6), &["k0"]);
+        let err = check_cycles(&h).unwrap_err().to_string();
+        assert_eq!(err.matches("actions are cycled").count(), MAX_CYCLES);
+        assert!(err.contains(&format!("only first {} cycles are reported", MAX_CYCLES)));
+    }
+
+    #[test]
+    fn unreachable_cycles_are_ignored() {
+        let mut actions = complete(6);
+        actions.push(("a".to_string(), vec!["b".to_string()]));
+        actions.push(("b".to_string(), vec!["a".to_string()]));
+        actions.push(("c".to_string(), vec![]));
+        let h = graph_owned(&actions, &["a"]);
+        // cycles among unreachable actions don't use up the limit
+        assert_eq!(schedule_errors(&h), ["a -> b -> a"]);
+
+        let h = graph_owned(&actions, &["c"]);
+        let wf = h.workflows().next().unwrap();
+        let order = schedule(&h, wf).unwrap();
+        assert_eq!(order, [h.find_action("c").unwrap()]);
+    }
+
+    /// `c` needs `b`, which needs `a`; `d` is independent
+    fn chain() -> Hir {
+        graph(
+            &[("a", &[]), ("b", &["a"]), ("c", &["b"]), ("d", &[])],
+            &["c", "d"],
+        )
+    }
+
+    fn names(h: &Hir, acts: &[ActionId]) -> Vec<String> {
+        acts.iter().map(|&act| h.action(act).name.clone()).collect()
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(ToString::to_string).collect()
+    }
+
+    /// Returns names of actions, chosen from the workflow
+    fn selected(h: &Hir, targets: &[&str], only: &[&str], skip: &[&str]) -> Vec<String> {
+        let selection =
+            Selection::new(h, &strings(targets), &strings(only), &strings(skip)).unwrap();
+        let wf = h.workflows().next().unwrap();
+        let chosen = selection.apply(&schedule(h, wf).unwrap());
+        selection.check(h, &chosen).unwrap();
+        names(h, &chosen)
+    }
+
+    #[test]
+    fn select_all() {
+        let h = chain();
+        assert_eq!(selected(&h, &[], &[], &[]), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn select_target_with_needs() {
+        let h = chain();
+        assert_eq!(selected(&h, &["c"], &[], &[]), ["a", "b", "c"]);
+        assert_eq!(selected(&h, &["b", "d"], &[], &[]), ["a", "b", "d"]);
+    }
+
+    #[test]
+    fn select_only() {
+        let h = chain();
+        assert_eq!(selected(&h, &[], &["b"], &[]), ["b"]);
+    }
+
+    #[test]
+    fn skipped_dependency_does_not_block() {
+        let h = chain();
+        let order: Vec<_> = ["a", "c"]
+            .iter()
+            .map(|name| h.find_action(name).unwrap())
+            .collect();
+        assert_eq!(names(&h, &order), selected(&h, &["c"], &[], &["b"]));
+        let progress = run(&h, &order, 1, |_| Outcome::Success);
+        assert_eq!(progress.outcome(order[1]), Some(Outcome::Success));
+        assert_eq!(progress.outcome(h.find_action("b").unwrap()), None);
+    }
+
+    #[test]
+    fn bad_selection() {
+        let h = chain();
+        let err = Selection::new(&h, &strings(&["x"]), &[], &[])
+            .err()
+            .unwrap();
+        assert_eq!(err, "action 'x' is not defined");
+        let err = Selection::new(&h, &[], &[], &strings(&["x"]))
+            .err()
+            .unwrap();
+        assert_eq!(err, "action 'x' is not defined");
+
+        let selection = Selection::new(&h, &strings(&["c"]), &[], &strings(&["c"])).unwrap();
+        let wf = h.workflows().next().unwrap();
+        let chosen = selection.apply(&schedule(&h, wf).unwrap());
+        assert_eq!(names(&h, &chosen), ["a", "b"]);
+        assert_eq!(
+            selection.check(&h, &chosen).unwrap_err(),
+            "action 'c' is not run by any selected workflow"
+        );
     }
-    out
 }
@@ -0,0 +1,233 @@
+use crate::hir::{ActionId, Hir};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Secret values, available to actions
+pub struct Secrets {
+    values: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    /// Secret, requested from environment, is not set
+    NotInEnv(String),
+    /// Secrets without values, with names of actions which use them
+    Missing(BTreeMap<String, BTreeSet<String>>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Read(path, err) => write!(f, "failed read {}: {}", path.display(), err),
+            Error::Parse(path, err) => write!(f, "failed parse {}: {}", path.display(), err),
+            Error::NotInEnv(name) => write!(f, "secret {} is not set in environment", name),
+            Error::Missing(missing) => {
+                writeln!(f, "secrets have no value:")?;
+                for (secret, actions) in missing {
+                    let actions: Vec<_> = actions.iter().map(String::as_str).collect();
+                    writeln!(f, "  {} (used by {})", secret, actions.join(", "))?;
+                }
+                write!(f, "hint: provide secrets with --secrets-file or --secret")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn unquote(s: &str) -> &str {
+    for quote in &['"', '\''] {
+        if s.len() >= 2 && s.starts_with(*quote) && s.ends_with(*quote) {
+            return &s[1..s.len() - 1];
+        }
+    }
+    s
+}
+
+/// Parses file with `NAME=value` lines
+fn parse_dotenv(data: &str) -> Result<HashMap<String, String>, String> {
+    let mut out = HashMap::new();
+    for (line_num, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let eq_pos = line
+            .find('=')
+            .ok_or_else(|| format!("line {}: expected NAME=value", line_num + 1))?;
+        let name = line[..eq_pos].trim();
+        let value = unquote(line[eq_pos + 1..].trim());
+        out.insert(name.to_string(), value.to_string());
+    }
+    Ok(out)
+}
+
+/// Parses JSON object with string values
+fn parse_json(data: &str) -> Result<HashMap<String, String>, String> {
+    serde_json::from_str(data).map_err(|err| err.to_string())
+}
+
+impl Secrets {
+    pub fn new(values: HashMap<String, String>) -> Secrets {
+        Secrets { values }
+    }
+
+    /// Loads secrets from `file` (JSON or dotenv) and forwards `from_env` variables from host environment
+    pub fn load(file: Option<&Path>, from_env: &[String]) -> Result<Secrets, Error> {
+        let mut values = HashMap::new();
+        if let Some(file) = file {
+            let data = std::fs::read_to_string(file)
+                .map_err(|err| Error::Read(file.to_path_buf(), err))?;
+            let is_json = file.extension().is_some_and(|ext| ext == "json");
+            let parsed = if is_json {
+                parse_json(&data)
+            } else {
+                parse_dotenv(&data)
+            };
+            values = parsed.map_err(|err| Error::Parse(file.to_path_buf(), err))?;
+        }
+        for name in from_env {
+            let val = std::env::var(name).map_err(|_| Error::NotInEnv(name.clone()))?;
+            values.insert(name.clone(), val);
+        }
+        Ok(Secrets { values })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &str> {
+        self.values.values().map(String::as_str)
+    }
+
+    /// Checks that all secrets, declared by `actions`, have values
+    pub fn check(&self, h: &Hir, actions: &[ActionId]) -> Result<(), Error> {
+        // secret name -> actions which use it
+        let mut missing: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for &act in actions {
+            let act = h.action(act);
+            for secret in &act.secrets {
+                if self.get(secret).is_none() {
+                    missing
+                        .entry(secret.clone())
+                        .or_default()
+                        .insert(act.name.clone());
+                }
+            }
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Missing(missing))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::{self, diag::Source, parser};
+
+    #[test]
+    fn dotenv() {
+        let values = parse_dotenv(
+            r#"
+# comment
+TOKEN=abc
+export EXPORTED = exported
+DOUBLE="two words"
+SINGLE='it''s'
+  # indented comment
+EMPTY=
+WITH_EQ=a=b
+"#,
+        )
+        .unwrap();
+        let expected: HashMap<_, _> = [
+            ("TOKEN", "abc"),
+            ("EXPORTED", "exported"),
+            ("DOUBLE", "two words"),
+            ("SINGLE", "it''s"),
+            ("EMPTY", ""),
+            ("WITH_EQ", "a=b"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn malformed_dotenv() {
+        let err = parse_dotenv("A=1\n\nNO_VALUE\n").unwrap_err();
+        assert_eq!(err, "line 3: expected NAME=value");
+    }
+
+    #[test]
+    fn json() {
+        let values = parse_json(r#"{"TOKEN": "abc", "MULTI": "a\nb"}"#).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["TOKEN"], "abc");
+        assert_eq!(values["MULTI"], "a\nb");
+        assert!(parse_json(r#"{"NUMBER": 1}"#).is_err());
+        assert!(parse_json("[]").is_err());
+    }
+
+    #[test]
+    fn check_reports_missing_secrets_with_actions() {
+        let src = r#"
+workflow "ci" {
+  on = "push"
+  resolves = ["a", "b", "c"]
+}
+
+action "a" {
+  uses = "docker://alpine"
+  secrets = ["TOKEN", "KEY"]
+}
+
+action "b" {
+  uses = "docker://alpine"
+  secrets = ["KEY"]
+}
+
+action "c" {
+  uses = "docker://alpine"
+  secrets = ["OTHER"]
+}
+"#;
+        let defs = parser::parse(&Source::new("main.workflow", src.to_string())).unwrap();
+        let h = hir::build(defs).unwrap();
+        let mut values = HashMap::new();
+        values.insert("TOKEN".to_string(), "abc".to_string());
+        let secrets = Secrets::new(values);
+        let ids: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|name| h.find_action(name).unwrap())
+            .collect();
+
+        match secrets.check(&h, &ids) {
+            Err(Error::Missing(missing)) => {
+                let missing: Vec<_> = missing
+                    .iter()
+                    .map(|(secret, actions)| {
+                        let actions: Vec<_> = actions.iter().map(String::as_str).collect();
+                        (secret.as_str(), actions)
+                    })
+                    .collect();
+                assert_eq!(missing, [("KEY", vec!["a", "b"]), ("OTHER", vec!["c"])]);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        // only actions, which will run, are checked
+        assert!(secrets.check(&h, &ids[..0]).is_ok());
+        secrets.check(&h, &ids[..1]).unwrap_err();
+    }
+}